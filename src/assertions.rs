@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Duration;
+
+/// Fails when `elapsed` is not strictly below `limit_ms`.
+pub fn check_duration(elapsed: Duration, limit_ms: u64) -> Result<()> {
+    let actual_ms = elapsed.as_millis();
+    if actual_ms >= u128::from(limit_ms) {
+        anyhow::bail!(
+            "Duration exceeded: took {}ms, limit is {}ms",
+            actual_ms,
+            limit_ms
+        );
+    }
+    Ok(())
+}
+
+/// Fails when `output` does not consist of exactly `expected` lines.
+pub fn assert_line_count(output: &str, expected: usize) -> Result<()> {
+    let actual = output.lines().count();
+    if actual != expected {
+        anyhow::bail!(
+            "Output line count mismatch: got {} lines, expected {}",
+            actual,
+            expected
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless `code` is one of the `valid` exit codes.
+pub fn assert_exit_code_in(code: i32, valid: &[i32]) -> Result<()> {
+    if !valid.contains(&code) {
+        let valid: Vec<_> = valid.iter().map(i32::to_string).collect();
+        anyhow::bail!(
+            "Exit code mismatch: got {}, expected one of {}",
+            code,
+            valid.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Fails unless `output` parses as JSON that satisfies the schema at `schema_path`.
+pub fn assert_json_output(output: &str, schema_path: &Path) -> Result<()> {
+    let schema_text = std::fs::read_to_string(schema_path)
+        .with_context(|| format!("Failed to read JSON schema: {}", schema_path.display()))?;
+    let schema: serde_json::Value = serde_json::from_str(&schema_text)
+        .with_context(|| format!("Invalid JSON schema: {}", schema_path.display()))?;
+    let validator = jsonschema::validator_for(&schema)
+        .map_err(|e| anyhow::anyhow!("Invalid JSON schema {}: {}", schema_path.display(), e))?;
+    let instance: serde_json::Value =
+        serde_json::from_str(output).context("Interpreter output is not valid JSON")?;
+
+    let errors: Vec<String> = validator
+        .iter_errors(&instance)
+        .map(|e| format!("  {}: {}", e.instance_path(), e))
+        .collect();
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Output does not match JSON schema {}:\n{}",
+            schema_path.display(),
+            errors.join("\n")
+        );
+    }
+    Ok(())
+}
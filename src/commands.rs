@@ -1,9 +1,29 @@
-use anyhow::Result;
-use clap::Subcommand;
+use anyhow::{Context, Result};
+use clap::{ArgGroup, Args, Subcommand, ValueEnum};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
+use crate::assertions::{
+    assert_exit_code_in, assert_json_output, assert_line_count, check_duration,
+};
 use crate::config::{load_config, save_config, XnConfig};
+use crate::manifest::{
+    build_manifest, sha256_file, write_ide_config, write_manifest, IdeConfig, IDE_CONFIG_FILE,
+};
+use crate::process::{
+    apply_line_buffer_settings, apply_stdio_redirects, build_prepended_path, conditional_capture,
+    container_command, decode_base64_to_stdin, decode_hex_to_stdin, drive_child, enable_core_dumps,
+    inherit_fds, isolate_namespaces, limit_process_count, pipe_to_stdin, prepend_tracer,
+    report_core_dump, run_buffered, run_hook, set_core_dump_dir, syscall_tracer, tee, ChildIo,
+    HeartbeatThread, StdinFeed,
+};
+use crate::source::{declared_functions, InjectedFunction};
+use crate::wasm::{append_custom_section, count_exported_functions};
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
@@ -13,33 +33,16 @@ pub enum Commands {
         name: String,
     },
     /// Build (compile) XN sources to WASM
-    Build {
-        /// Optional single source file (otherwise we gather from src/)
-        #[arg(short, long)]
-        source: Option<PathBuf>,
-        /// Output WASM file
-        #[arg(short, long, default_value = "out/output.wasm")]
-        output: PathBuf,
-    },
+    Build(BuildArgs),
     /// Run (interpret) one or more XN files
-    Run {
-        /// If empty, we'll gather .xn files from src/
-        #[arg()]
-        files: Vec<PathBuf>,
-        /// Entrypoint file if needed
-        #[arg(short, long)]
-        entry: Option<PathBuf>,
-    },
+    Run(RunArgs),
     /// Execute a compiled WASM file on the XN WASM VM
-    Vm {
-        /// The `.wasm` file to run
-        wasm_file: PathBuf,
-        /// Arguments to pass to the VM
-        #[arg()]
-        args: Vec<String>,
-    },
+    Vm(VmArgs),
     /// Read or update config file key-value pairs
+    #[command(args_conflicts_with_subcommands = true)]
     Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
         /// The config key (e.g., "compiler_path", "vm_path")
         key: Option<String>,
         /// The value to set. Omit to get the current value of `key`.
@@ -47,6 +50,405 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the config file path relative to a base directory
+    PathRelative {
+        /// Directory the printed path is relative to
+        base: PathBuf,
+    },
+    /// Substitute ${key} placeholders in a template with config values
+    Interpolate {
+        /// Template text, e.g. "${compiler_path} --help"
+        template: String,
+    },
+    /// List the environment variables carrier reads or sets, with their current values
+    ListEnvVars,
+}
+
+#[derive(Args, Debug)]
+pub struct BuildArgs {
+    /// Optional single source file (otherwise we gather from src/)
+    #[arg(short, long)]
+    pub source: Option<PathBuf>,
+    /// Project config to build with instead of carrier.toml
+    #[arg(long)]
+    pub manifest_path: Option<PathBuf>,
+    /// Glob pattern selecting the source files, e.g. "src/**/*.xn"
+    #[arg(long, conflicts_with = "source")]
+    pub source_glob: Option<String>,
+    /// Fail if more than this many source files are gathered
+    #[arg(long, value_name = "N")]
+    pub source_file_limit: Option<u32>,
+    /// Output WASM file
+    #[arg(short, long, default_value = "out/output.wasm")]
+    pub output: PathBuf,
+    /// Extra argument passed verbatim to the compiler (repeatable)
+    #[arg(long = "compiler-args", allow_hyphen_values = true)]
+    pub compiler_args_raw: Vec<String>,
+    /// Print line and byte counts for each source file before building
+    #[arg(long)]
+    pub report_source_stats: bool,
+    /// Warn when a function name is defined in more than one source file
+    #[arg(long)]
+    pub report_duplicate_symbols: bool,
+    /// Fail before compiling if any source line contains a TODO comment
+    #[arg(long)]
+    pub error_on_todo: bool,
+    /// Print the SHA-256 of each source file in sha256sum format
+    #[arg(long)]
+    pub report_file_hashes: bool,
+    /// Write the --report-file-hashes output to this file instead of stdout
+    #[arg(long, requires = "report_file_hashes")]
+    pub hash_output: Option<PathBuf>,
+    /// Append a function to the merged source, given as name:signature:body (repeatable)
+    #[arg(long = "inject-function", value_name = "NAME:SIGNATURE:BODY")]
+    pub inject_functions: Vec<String>,
+    /// Print the merged source to stdout and exit without compiling
+    #[arg(long)]
+    pub print_merged_source: bool,
+    /// Environment variable for the compiler process, as KEY=VALUE (repeatable)
+    #[arg(long)]
+    pub compiler_env: Vec<String>,
+    /// Build identifier passed to the compiler as BUILD_ID (`git` uses the commit hash)
+    #[arg(long)]
+    pub build_id: Option<String>,
+    /// Build an instrumented binary that records a PGO profile
+    #[arg(long, conflicts_with = "pgo_use")]
+    pub pgo_instrument: bool,
+    /// Optimize using previously recorded PGO profile data
+    #[arg(long, value_name = "PROFILE")]
+    pub pgo_use: Option<PathBuf>,
+    /// Set SOURCE_DATE_EPOCH from a date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_name = "DATE")]
+    pub source_date: Option<String>,
+    /// Pass -DBUILD_NUMBER=<N> and name the output output-b<N>.wasm; without N, the last number in out/.build-number plus one
+    #[arg(long, value_name = "N")]
+    pub build_number: Option<Option<u32>>,
+    /// Sort sources, pin SOURCE_DATE_EPOCH and the output mtime, and ask the compiler for deterministic output
+    #[arg(long)]
+    pub reproducible: bool,
+    /// Build for wasm32-wasi using the `[wasi_defaults]` settings from carrier.toml
+    #[arg(long)]
+    pub target_wasm32_wasi: bool,
+    /// Build a binary instrumented for code coverage
+    #[arg(long)]
+    pub coverage_instrument: bool,
+    /// Link-time optimization mode passed to the compiler
+    #[arg(long = "with-lto", value_name = "MODE", value_parser = ["thin", "full", "off"])]
+    pub lto: Option<String>,
+    /// Append the compiler's stdout and stderr to this file while still showing them
+    #[arg(long)]
+    pub compiler_log_file: Option<PathBuf>,
+    /// Pipe the merged source to the compiler's stdin instead of writing out/output.xn
+    #[arg(long)]
+    pub compiler_stdin_source: bool,
+    /// Compile `precompile_includes` from carrier.toml once, cache them and link the cached objects
+    #[arg(long)]
+    pub precompile_includes: bool,
+    /// Compile each source file into its own module instead of merging them
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "inject_functions",
+            "compiler_stdin_source",
+            "embed_config",
+            "output_manifest",
+            "report_symbol_count",
+            "copy_to"
+        ]
+    )]
+    pub compile_each_separately: bool,
+    /// Embed carrier.toml in the output as a `xenon-config` custom section
+    #[arg(long)]
+    pub embed_config: bool,
+    /// Embed the config without redacting sensitive values
+    #[arg(long, requires = "embed_config")]
+    pub embed_config_full: bool,
+    /// Print the number of functions the built module exports
+    #[arg(long)]
+    pub report_symbol_count: bool,
+    /// Copy the output WASM to this path after a successful build
+    #[arg(long)]
+    pub copy_to: Option<PathBuf>,
+    /// Rename the output after the build, e.g. "{project_name}-{version}-{hash}.wasm"
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with = "compile_each_separately"
+    )]
+    pub out_name_template: Option<String>,
+    /// Write the Xenon language server project file (.xenon-project.json)
+    #[arg(long)]
+    pub generate_ide_config: bool,
+    /// Write a JSON manifest of the build inputs and outputs to this file
+    #[arg(long)]
+    pub output_manifest: Option<PathBuf>,
+    /// Shell command to run after a successful build
+    #[arg(long)]
+    pub on_success: Option<String>,
+    /// Shell command to run after a failed build
+    #[arg(long)]
+    pub on_failure: Option<String>,
+}
+
+/// How strongly `carrier run` isolates the interpreter from the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum IsolationLevel {
+    /// Run the interpreter directly
+    #[default]
+    None,
+    /// New PID and network namespaces (Linux, needs root)
+    Namespace,
+    /// Inside the OCI bundle configured in carrier.toml
+    Container,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("stdin_source").multiple(false)))]
+pub struct RunArgs {
+    /// If empty, we'll gather .xn files from src/
+    #[arg()]
+    pub files: Vec<PathBuf>,
+    /// Entrypoint file if needed
+    #[arg(short, long)]
+    pub entry: Option<PathBuf>,
+    /// Print the files passed to the interpreter to stderr before running
+    #[arg(long, overrides_with = "no_print_files")]
+    pub print_files: bool,
+    /// Do not print the files passed to the interpreter (overrides --print-files)
+    #[arg(long, overrides_with = "print_files")]
+    pub no_print_files: bool,
+    /// Run the interpreter in the parent directory of this path
+    #[arg(long, value_name = "PATH")]
+    pub inherit_cwd_from: Option<PathBuf>,
+    /// Put this directory in front of the interpreter's PATH (repeatable)
+    #[arg(long, value_name = "DIR")]
+    pub prepend_to_path: Vec<PathBuf>,
+    /// Ask the interpreter to flush its stdout after every line
+    #[arg(long)]
+    pub line_buffered: bool,
+    /// Prefix each line of interpreter output with a UTC timestamp
+    #[arg(long)]
+    pub log_timestamps: bool,
+    /// Close the interpreter's stdin after this many milliseconds
+    #[arg(long = "stdin-close-after", value_name = "MS", group = "stdin_source")]
+    pub stdin_close_after_ms: Option<u64>,
+    /// Run this shell command to completion first and feed its output to the interpreter's stdin
+    #[arg(long, value_name = "COMMAND", group = "stdin_source")]
+    pub stdin_from_process_substitution: Option<String>,
+    /// Forward stdin line by line and close it when an empty line is read
+    #[arg(long, group = "stdin_source")]
+    pub stdin_eof_on_empty_line: bool,
+    /// Feed the stdout of this shell command to the interpreter's stdin
+    #[arg(long, value_name = "COMMAND", group = "stdin_source")]
+    pub stdin_generator: Option<String>,
+    /// Feed these hex-encoded bytes to the interpreter's stdin, then close it
+    #[arg(long, value_name = "HEX", group = "stdin_source")]
+    pub stdin_hex: Option<String>,
+    /// Feed these base64-encoded bytes to the interpreter's stdin, then close it
+    #[arg(long, value_name = "BASE64", group = "stdin_source")]
+    pub stdin_base64: Option<String>,
+    /// Write the interpreter's stderr to this file instead of the terminal
+    #[arg(long)]
+    pub capture_stderr: Option<PathBuf>,
+    /// Forward at most this many bytes of interpreter stderr, then stop the interpreter
+    #[arg(long, value_name = "N", conflicts_with = "capture_stderr")]
+    pub max_stderr_bytes: Option<u64>,
+    /// Save every byte forwarded to the interpreter's stdin to this file
+    #[arg(long, group = "stdin_source")]
+    pub stdin_record: Option<PathBuf>,
+    /// Run the interpreter under strace (Linux) or dtruss (macOS)
+    #[arg(long)]
+    pub trace_syscalls: bool,
+    /// Where --trace-syscalls writes the trace [default: out/run-trace.txt]
+    #[arg(long, requires = "trace_syscalls")]
+    pub trace_output: Option<PathBuf>,
+    /// Maximum number of processes the interpreter may create (Linux only)
+    #[arg(long)]
+    pub max_processes: Option<u64>,
+    /// Keep these file descriptors open in the interpreter (comma-separated)
+    #[arg(long = "inherit-fds", value_name = "FD", value_delimiter = ',')]
+    pub inherit_fds: Vec<u64>,
+    /// Allow the interpreter to write a core dump if it crashes (Unix only)
+    #[arg(long)]
+    pub core_dump_on_crash: bool,
+    /// Attach a CPU profiler (perf on Linux, sample on macOS) and write its report here
+    #[arg(long)]
+    pub cpu_profile_output: Option<PathBuf>,
+    /// Hold the interpreter's output back and only print it if the program fails
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "log_timestamps",
+            "capture_stderr",
+            "max_stderr_bytes",
+            "cpu_profile_output",
+            "stdin_close_after_ms",
+            "stdin_record",
+            "stdin_eof_on_empty_line"
+        ]
+    )]
+    pub output_on_failure_only: bool,
+    /// Write the interpreter's PID to this file while it runs
+    #[arg(long, value_name = "PATH", conflicts_with = "output_on_failure_only")]
+    pub write_pid_file: Option<PathBuf>,
+    /// Save the interpreter's stdout and stderr to this file if it exits non-zero
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "log_timestamps",
+            "capture_stderr",
+            "max_stderr_bytes",
+            "cpu_profile_output",
+            "stdin_close_after_ms",
+            "stdin_record",
+            "stdin_eof_on_empty_line",
+            "output_on_failure_only",
+            "write_pid_file",
+            "assert_output_line_count",
+            "assert_json_output",
+            "stdout_to_stderr"
+        ]
+    )]
+    pub capture_output_if_failure: Option<PathBuf>,
+    /// Isolate the interpreter from the host
+    #[arg(long, value_enum, default_value_t = IsolationLevel::None)]
+    pub isolation_level: IsolationLevel,
+    /// Print a heartbeat line to stderr every this many milliseconds while the interpreter runs
+    #[arg(
+        long = "heartbeat-interval",
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub heartbeat_interval_ms: Option<u64>,
+    /// Fail if the interpreter runs for this many milliseconds or longer
+    #[arg(long = "assert-duration-less-than", value_name = "MS")]
+    pub assert_duration_less_than_ms: Option<u64>,
+    /// Fail unless the interpreter exits with one of these comma-separated codes
+    #[arg(long, value_name = "CODES", value_delimiter = ',')]
+    pub assert_exit_code_in: Vec<i32>,
+    /// Fail unless the interpreter prints exactly this many lines to stdout
+    #[arg(long, value_name = "N", conflicts_with = "log_timestamps")]
+    pub assert_output_line_count: Option<usize>,
+    /// Fail unless the interpreter's stdout is JSON matching this JSON schema file
+    #[arg(long, value_name = "SCHEMA", conflicts_with = "log_timestamps")]
+    pub assert_json_output: Option<PathBuf>,
+    /// Relay the interpreter's stdout to carrier's stderr
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "log_timestamps",
+            "output_on_failure_only",
+            "assert_output_line_count",
+            "assert_json_output"
+        ]
+    )]
+    pub stdout_to_stderr: bool,
+    /// Shell command to run when the interpreter exits with code 0
+    #[arg(long)]
+    pub on_success: Option<String>,
+    /// Shell command to run when the interpreter exits with a non-zero code
+    #[arg(long)]
+    pub on_failure: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct VmArgs {
+    /// The `.wasm` file to run
+    pub wasm_file: PathBuf,
+    /// Arguments to pass to the VM
+    #[arg()]
+    pub args: Vec<String>,
+    /// Put this directory in front of the VM's PATH (repeatable)
+    #[arg(long, value_name = "DIR")]
+    pub prepend_to_path: Vec<PathBuf>,
+    /// Ask the VM to flush its stdout after every line
+    #[arg(long)]
+    pub line_buffered: bool,
+    /// Prefix each line of VM output with a UTC timestamp
+    #[arg(long)]
+    pub log_timestamps: bool,
+    /// Run the VM under strace (Linux) or dtruss (macOS)
+    #[arg(long)]
+    pub trace_syscalls: bool,
+    /// Where --trace-syscalls writes the trace [default: out/vm-trace.txt]
+    #[arg(long, requires = "trace_syscalls")]
+    pub trace_output: Option<PathBuf>,
+    /// Write the VM's stderr to this file instead of the terminal
+    #[arg(long)]
+    pub capture_stderr: Option<PathBuf>,
+    /// Forward at most this many bytes of VM stderr, then stop the VM
+    #[arg(long, value_name = "N", conflicts_with = "capture_stderr")]
+    pub max_stderr_bytes: Option<u64>,
+    /// Hold the VM's output back and only print it if the program fails
+    #[arg(
+        long,
+        conflicts_with_all = ["log_timestamps", "capture_stderr", "max_stderr_bytes"]
+    )]
+    pub output_on_failure_only: bool,
+    /// Print a heartbeat line to stderr every this many milliseconds while the VM runs
+    #[arg(
+        long = "heartbeat-interval",
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub heartbeat_interval_ms: Option<u64>,
+    /// Write the VM's PID to this file while it runs
+    #[arg(long, value_name = "PATH", conflicts_with = "output_on_failure_only")]
+    pub write_pid_file: Option<PathBuf>,
+    /// Save the VM's stdout and stderr to this file if it exits non-zero
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "log_timestamps",
+            "capture_stderr",
+            "max_stderr_bytes",
+            "output_on_failure_only",
+            "write_pid_file",
+            "assert_output_line_count",
+            "stdout_to_stderr"
+        ]
+    )]
+    pub capture_output_if_failure: Option<PathBuf>,
+    /// Feed these hex-encoded bytes to the VM's stdin, then close it
+    #[arg(long, value_name = "HEX")]
+    pub stdin_hex: Option<String>,
+    /// Warn if the VM takes longer than this many milliseconds
+    #[arg(long = "assert-within-time", value_name = "MS")]
+    pub assert_within_time_ms: Option<u64>,
+    /// Fail instead of warning when --assert-within-time is exceeded
+    #[arg(long, requires = "assert_within_time_ms")]
+    pub strict: bool,
+    /// Fail if the VM runs for this many milliseconds or longer
+    #[arg(long = "assert-duration-less-than", value_name = "MS")]
+    pub assert_duration_less_than_ms: Option<u64>,
+    /// Fail unless the VM exits with one of these comma-separated codes
+    #[arg(long, value_name = "CODES", value_delimiter = ',')]
+    pub assert_exit_code_in: Vec<i32>,
+    /// Fail unless the VM prints exactly this many lines to stdout
+    #[arg(long, value_name = "N", conflicts_with = "log_timestamps")]
+    pub assert_output_line_count: Option<usize>,
+    /// Relay the VM's stdout to carrier's stderr
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "log_timestamps",
+            "output_on_failure_only",
+            "assert_output_line_count"
+        ]
+    )]
+    pub stdout_to_stderr: bool,
+    /// Shell command to run when the VM exits with code 0
+    #[arg(long)]
+    pub on_success: Option<String>,
+    /// Shell command to run when the VM exits with a non-zero code
+    #[arg(long)]
+    pub on_failure: Option<String>,
+}
+
 pub fn handle_init(name: String) -> Result<()> {
     std::fs::create_dir_all(&name)?;
 
@@ -90,34 +492,454 @@ fn main() -> i32 {
     Ok(())
 }
 
-pub fn handle_build(source: Option<PathBuf>, output: PathBuf) -> Result<()> {
-    let config = load_config("carrier.toml")?;
+pub fn handle_build(mut args: BuildArgs, no_ansi: bool) -> Result<()> {
+    let manifest_path = args
+        .manifest_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("carrier.toml"));
+    let config = load_config(manifest_path)?;
+
+    if let Some(date) = &args.source_date {
+        let epoch = parse_source_date(date)?;
+        eprintln!("SOURCE_DATE_EPOCH={} ({})", epoch, date);
+        std::env::set_var("SOURCE_DATE_EPOCH", epoch.to_string());
+    }
+    let reproducible = args.reproducible || config.reproducible;
+    let mut files = match (&args.source, &args.source_glob) {
+        (Some(src_path), _) => vec![src_path.clone()],
+        (None, Some(pattern)) => glob_xn_files(pattern)?,
+        (None, None) => gather_xn_files("src"),
+    };
+    if reproducible {
+        // directory walk order depends on the filesystem
+        files.sort();
+    }
+    if let Some(limit) = args.source_file_limit.or(config.source_file_limit) {
+        if files.len() > limit as usize {
+            anyhow::bail!(
+                "Too many source files: found {}, limit is {}",
+                files.len(),
+                limit
+            );
+        }
+    }
+
+    if args.report_source_stats {
+        print_source_stats(&files)?;
+    }
+    if args.report_duplicate_symbols || config.report_duplicate_symbols {
+        report_duplicate_symbols(&files)?;
+    }
+    if args.error_on_todo || config.error_on_todo {
+        check_todo_comments(&files, &config.todo_pattern)?;
+    }
+
+    if args.report_file_hashes {
+        report_file_hashes(&files, args.hash_output.as_deref())?;
+    }
+
+    let injected = args
+        .inject_functions
+        .iter()
+        .map(|spec| InjectedFunction::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    if args.print_merged_source {
+        print!("{}", merge_xn_sources(&files, &injected)?);
+        return Ok(());
+    }
 
     std::fs::create_dir_all("out")?;
 
-    // if not specify a `--source`, concatenate all .xn from src/
-    let source_to_compile = if let Some(src_path) = source {
-        src_path
+    let build_number = match args.build_number {
+        Some(number) => Some(next_build_number(number)?),
+        None => None,
+    };
+    if let Some(number) = build_number {
+        let stem = args
+            .output
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut name = format!("{}-b{}", stem, number);
+        if let Some(ext) = args.output.extension() {
+            name = format!("{}.{}", name, ext.to_string_lossy());
+        }
+        args.output.set_file_name(name);
+    }
+
+    // command-line entries come last so they override the config
+    let compiler_env = config
+        .compiler_env
+        .iter()
+        .chain(&args.compiler_env)
+        .map(|pair| parse_env_pair(pair))
+        .collect::<Result<Vec<_>>>()?;
+
+    let log_file = match args
+        .compiler_log_file
+        .as_ref()
+        .or(config.compiler_log_file.as_ref())
+    {
+        Some(path) => Some(
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open compiler log: {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let mut compiler_flags = Vec::new();
+    let mut merged_files = files.clone();
+    if args.precompile_includes {
+        for include in &config.precompile_includes {
+            let object = precompile_include(&config, include, &compiler_env, log_file.as_ref())?;
+            merged_files.retain(|f| !same_file(f, include));
+            compiler_flags.push(config.compiler_precompiled_flag.clone());
+            compiler_flags.push(object.to_string_lossy().into_owned());
+        }
+    }
+
+    if let Some(id) = args.build_id {
+        let id = resolve_build_id(id)?;
+        compiler_flags.push(format!("-DBUILD_ID=\"{}\"", id));
+    }
+    if let Some(number) = build_number {
+        compiler_flags.push(format!("-DBUILD_NUMBER={}", number));
+    }
+    if args.pgo_instrument {
+        compiler_flags.push(config.compiler_pgo_generate_flag.clone());
+    }
+    if let Some(profile) = &args.pgo_use {
+        if !profile.is_file() {
+            anyhow::bail!("PGO profile data not found: {}", profile.display());
+        }
+        compiler_flags.push(format!(
+            "{}={}",
+            config.compiler_pgo_use_flag,
+            profile.display()
+        ));
+    }
+    if args.target_wasm32_wasi {
+        let wasi = config.wasi_defaults.clone().unwrap_or_default();
+        compiler_flags.extend(wasi.compiler_flags());
+    }
+    if args.coverage_instrument {
+        std::fs::create_dir_all(&config.coverage_output_dir)?;
+        let dir = config.coverage_output_dir.to_string_lossy();
+        compiler_flags.extend(
+            config
+                .compiler_coverage_flags
+                .iter()
+                .map(|flag| flag.replace("{dir}", &dir)),
+        );
+    }
+    // debug builds default to LTO off, which is the compiler's own default,
+    // so only an explicit mode passes `off`
+    let profile_lto = (config.profile == "release").then_some("thin");
+    let lto = args
+        .lto
+        .as_deref()
+        .or(config.lto.as_deref())
+        .or(profile_lto);
+    if let Some(mode) = lto {
+        if !matches!(mode, "thin" | "full" | "off") {
+            anyhow::bail!("Unknown LTO mode `{}` (expected thin, full or off)", mode);
+        }
+        compiler_flags.push(config.compiler_lto_flag_format.replace("{}", mode));
+    }
+    if reproducible {
+        compiler_flags.push(config.compiler_reproducible_flag.clone());
+    }
+    if no_ansi {
+        compiler_flags.push(config.compiler_no_color_flag.clone());
+    }
+    // raw args are never split, so flags with spaces or quotes survive intact
+    compiler_flags.extend(args.compiler_args_raw.iter().cloned());
+
+    if args.generate_ide_config {
+        let ide_config = IdeConfig {
+            source_dir: PathBuf::from("src"),
+            output_dir: args
+                .output
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            compiler_path: config.compiler_path.clone(),
+            compiler_flags: compiler_flags.clone(),
+            source_files: files.clone(),
+        };
+        write_ide_config(&ide_config, IDE_CONFIG_FILE)?;
+        println!("IDE config written -> {}", IDE_CONFIG_FILE);
+    }
+
+    let source_date_epoch = if reproducible {
+        let epoch = std::env::var("SOURCE_DATE_EPOCH").unwrap_or_else(|_| "0".to_string());
+        let secs = epoch
+            .parse::<u64>()
+            .with_context(|| format!("Invalid SOURCE_DATE_EPOCH: {}", epoch))?;
+        Some(secs)
+    } else {
+        None
+    };
+    let compiler_command = |source: &Path, output: &Path| {
+        let mut cmd = std::process::Command::new(&config.compiler_path);
+        cmd.arg(source)
+            .arg("-o")
+            .arg(output)
+            .args(&compiler_flags)
+            .envs(compiler_env.iter().copied());
+        if let Some(secs) = source_date_epoch {
+            cmd.env("SOURCE_DATE_EPOCH", secs.to_string());
+        }
+        cmd
+    };
+
+    if args.compile_each_separately {
+        if !config.supports_separate_compilation {
+            anyhow::bail!(
+                "--compile-each-separately requires supports_separate_compilation = true in carrier.toml"
+            );
+        }
+        if config.deploy_path.is_some() {
+            eprintln!("Warning: deploy_path is ignored with --compile-each-separately");
+        }
+        let out_dir = args.output.parent().unwrap_or(Path::new("out"));
+        let started = Instant::now();
+        let mut failed = 0;
+        for file in &merged_files {
+            let relative = file.strip_prefix("src").unwrap_or(file);
+            let output = out_dir.join(relative).with_extension("wasm");
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut cmd = compiler_command(file, &output);
+            let status = run_compiler(&mut cmd, None, log_file.as_ref())
+                .with_context(|| format!("Failed to compile {}", file.display()))?;
+            if status.success() {
+                println!("Build finished -> {}", output.display());
+                if let Some(secs) = source_date_epoch {
+                    set_mtime(&output, secs)?;
+                }
+            } else {
+                eprintln!("Compiling {} failed with {}", file.display(), status);
+                failed += 1;
+            }
+        }
+        let duration_ms = started.elapsed().as_millis().to_string();
+
+        let hook = if failed == 0 {
+            args.on_success.or(config.on_success.clone())
+        } else {
+            args.on_failure.or(config.on_failure.clone())
+        };
+        run_build_hook(hook, out_dir, &config.project_name, &duration_ms)?;
+        if failed > 0 {
+            anyhow::bail!(
+                "{} of {} files failed to compile",
+                failed,
+                merged_files.len()
+            );
+        }
+        return Ok(());
+    }
+
+    let stdin_source = if args.compiler_stdin_source {
+        if !config.compiler_supports_stdin {
+            anyhow::bail!(
+                "--compiler-stdin-source requires compiler_supports_stdin = true in carrier.toml"
+            );
+        }
+        Some(merge_xn_sources(&merged_files, &injected)?)
     } else {
-        let concatenated_file = concatenate_xn_files("src")?;
-        concatenated_file
+        None
     };
 
-    std::process::Command::new(&config.compiler_path)
-        .arg(source_to_compile)
-        .arg("-o")
-        .arg(&output)
-        .spawn()?
-        .wait()?;
+    // a lone `--source` is compiled as is, everything else goes through the merge
+    let source_to_compile = match &args.source {
+        _ if stdin_source.is_some() => PathBuf::from("-"),
+        Some(src_path) if injected.is_empty() => src_path.clone(),
+        _ => concatenate_xn_files(&merged_files, &injected)?,
+    };
+
+    let mut cmd = compiler_command(&source_to_compile, &args.output);
+    let started = Instant::now();
+    let status = run_compiler(&mut cmd, stdin_source, log_file.as_ref())?;
+    let duration_ms = started.elapsed().as_millis().to_string();
+
+    println!("Build finished -> {}", args.output.display());
 
-    println!("Build finished -> {}", output.display());
+    if args.embed_config && status.success() {
+        let embedded = if args.embed_config_full {
+            config.clone()
+        } else {
+            config.redacted()
+        };
+        let serialized = toml::to_string_pretty(&embedded)?;
+        append_custom_section(&args.output, "xenon-config", serialized.as_bytes())?;
+    }
+
+    if let (Some(template), true) = (&args.out_name_template, status.success()) {
+        let name = render_out_name(template, &config, &args.output, source_date_epoch)?;
+        let renamed = args.output.with_file_name(name);
+        if let Some(parent) = renamed.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&args.output, &renamed)?;
+        println!("Output renamed -> {}", renamed.display());
+        args.output = renamed;
+    }
+
+    // last, after everything that rewrites the output
+    if let (Some(secs), true) = (source_date_epoch, status.success()) {
+        set_mtime(&args.output, secs)?;
+    }
+
+    if args.report_symbol_count && status.success() {
+        let exported = count_exported_functions(&args.output)?;
+        println!("Exported functions: {}", exported);
+        if let Some(max) = config.max_exported_symbols {
+            if exported > max {
+                eprintln!(
+                    "Warning: {} exported functions exceed max_exported_symbols ({})",
+                    exported, max
+                );
+            }
+        }
+    }
+
+    let copy_to = args.copy_to.as_ref().or(config.deploy_path.as_ref());
+    if let (Some(dest), true) = (copy_to, status.success()) {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&args.output, dest)
+            .with_context(|| format!("Failed to copy output to {}", dest.display()))?;
+        println!("Copied -> {}", dest.display());
+    }
+
+    if let Some(manifest_path) = &args.output_manifest {
+        if status.success() {
+            let manifest = build_manifest(&files, &args.output, &cmd)?;
+            write_manifest(&manifest, manifest_path)?;
+            println!("Manifest written -> {}", manifest_path.display());
+        } else {
+            eprintln!("Warning: build failed, skipping manifest");
+        }
+    }
+
+    let hook = if status.success() {
+        args.on_success.or(config.on_success)
+    } else {
+        args.on_failure.or(config.on_failure)
+    };
+    run_build_hook(hook, &args.output, &config.project_name, &duration_ms)
+}
+
+/// Spawns the compiler. With `log_file`, its stdout and stderr are also
+/// appended there; `stdin_source` is written to its stdin.
+fn run_compiler(
+    cmd: &mut std::process::Command,
+    stdin_source: Option<String>,
+    log_file: Option<&File>,
+) -> Result<std::process::ExitStatus> {
+    if stdin_source.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    if log_file.is_some() {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let mut child = cmd.spawn()?;
+    let mut loggers = Vec::new();
+    if let Some(file) = log_file {
+        if let Some(stdout) = child.stdout.take() {
+            let copy = file.try_clone()?;
+            loggers.push(std::thread::spawn(move || {
+                tee(stdout, std::io::stdout(), copy)
+            }));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let copy = file.try_clone()?;
+            loggers.push(std::thread::spawn(move || {
+                tee(stderr, std::io::stderr(), copy)
+            }));
+        }
+    }
+    let writer = child
+        .stdin
+        .take()
+        .zip(stdin_source)
+        .map(|(mut stdin, source)| std::thread::spawn(move || stdin.write_all(source.as_bytes())));
+    let status = child.wait()?;
+    for logger in loggers {
+        logger.join().expect("compiler log thread panicked")?;
+    }
+    if let Some(writer) = writer {
+        let written = writer.join().expect("source writer thread panicked");
+        // a compiler that failed early may have closed its stdin on purpose
+        if status.success() {
+            written.context("Failed to write the source to the compiler's stdin")?;
+        }
+    }
+    Ok(status)
+}
+
+fn set_mtime(path: &Path, secs: u64) -> Result<()> {
+    let mtime = std::time::UNIX_EPOCH + Duration::from_secs(secs);
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(mtime)?;
     Ok(())
 }
 
-pub fn handle_run(mut files: Vec<PathBuf>, entry: Option<PathBuf>) -> Result<()> {
+fn run_build_hook(
+    hook: Option<String>,
+    output: &Path,
+    project_name: &str,
+    duration_ms: &str,
+) -> Result<()> {
+    if let Some(hook) = hook {
+        let output_path = output.to_string_lossy();
+        run_hook(
+            &hook,
+            &[
+                ("CARRIER_OUTPUT_PATH", &output_path),
+                ("CARRIER_PROJECT_NAME", project_name),
+                ("CARRIER_BUILD_DURATION_MS", duration_ms),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn handle_run(mut args: RunArgs) -> Result<()> {
     let config = load_config("carrier.toml")?;
 
+    if args.isolation_level == IsolationLevel::Container {
+        // the bundle's config.json defines the process, so these would be dropped
+        let unsupported: Vec<&str> = [
+            (!args.files.is_empty(), "source files"),
+            (args.entry.is_some(), "--entry"),
+            (!args.prepend_to_path.is_empty(), "--prepend-to-path"),
+            (args.inherit_cwd_from.is_some(), "--inherit-cwd-from"),
+            (args.line_buffered, "--line-buffered"),
+        ]
+        .into_iter()
+        .filter_map(|(given, name)| given.then_some(name))
+        .collect();
+        if !unsupported.is_empty() {
+            anyhow::bail!(
+                "--isolation-level container runs the process defined by the bundle's config.json and cannot apply {}",
+                unsupported.join(", ")
+            );
+        }
+    }
+
     // If the user does not pass any files, gather everything under src/
+    let mut files = args.files;
     if files.is_empty() {
         files = gather_xn_files("src");
     }
@@ -127,29 +949,341 @@ pub fn handle_run(mut files: Vec<PathBuf>, entry: Option<PathBuf>) -> Result<()>
         return Ok(());
     }
 
-    let mut cmd = std::process::Command::new(&config.interpreter_path);
+    if args.print_files && !args.no_print_files {
+        for f in &files {
+            eprintln!("{}", f.display());
+        }
+    }
+
+    // with a different working directory, relative paths would no longer resolve
+    let working_dir = match &args.inherit_cwd_from {
+        Some(path) => {
+            files = files
+                .iter()
+                .map(std::path::absolute)
+                .collect::<std::io::Result<_>>()?;
+            args.entry = args.entry.map(std::path::absolute).transpose()?;
+            let parent = std::path::absolute(path)?
+                .parent()
+                .map(Path::to_path_buf)
+                .with_context(|| format!("{} has no parent directory", path.display()))?;
+            Some(parent)
+        }
+        None => None,
+    };
+
+    let mut cmd = if args.isolation_level == IsolationLevel::Container {
+        container_command(&config)?
+    } else {
+        let mut cmd = std::process::Command::new(&config.interpreter_path);
+        for f in &files {
+            cmd.arg(f);
+        }
+        if let Some(entry_file) = args.entry {
+            cmd.arg("-e").arg(entry_file);
+        }
+        if args.line_buffered {
+            apply_line_buffer_settings(&mut cmd, &config, &config.interpreter_line_buffer_flag);
+        }
+        cmd
+    };
+    if let Some(dir) = &working_dir {
+        cmd.current_dir(dir);
+    }
+    if !args.prepend_to_path.is_empty() {
+        cmd.env("PATH", build_prepended_path(&args.prepend_to_path)?);
+    }
+    // wrap first: prepend_tracer copies the command line but not pre_exec hooks
+    let trace_output = if args.trace_syscalls {
+        let output = args
+            .trace_output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("out/run-trace.txt"));
+        // the tracer resolves it from the interpreter's working directory
+        let output = match &working_dir {
+            Some(_) => std::path::absolute(output)?,
+            None => output,
+        };
+        match syscall_tracer(&config) {
+            Some(tracer) => {
+                cmd = prepend_tracer(&cmd, tracer, &output)?;
+                Some(output)
+            }
+            None => {
+                eprintln!(
+                    "Warning: --trace-syscalls is only supported on Linux and macOS, ignoring it"
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if args.isolation_level == IsolationLevel::Namespace {
+        isolate_namespaces(&mut cmd)?;
+    }
+
+    let stdin_feed = if let Some(ms) = args.stdin_close_after_ms {
+        Some(StdinFeed::CloseAfter(Duration::from_millis(ms)))
+    } else if let Some(path) = &args.stdin_record {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create stdin record: {}", path.display()))?;
+        Some(StdinFeed::Record(file))
+    } else if args.stdin_eof_on_empty_line {
+        Some(StdinFeed::UntilEmptyLine)
+    } else {
+        None
+    };
+    let generator = match &args.stdin_generator {
+        Some(command) => {
+            let mut generator = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .stdout(Stdio::piped())
+                .spawn()
+                .with_context(|| format!("Failed to start stdin generator `{}`", command))?;
+            let stdout = generator.stdout.take().expect("generator stdout is piped");
+            cmd.stdin(Stdio::from(stdout));
+            Some(generator)
+        }
+        None => None,
+    };
+    if let Some(command) = &args.stdin_from_process_substitution {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run stdin command `{}`", command))?;
+        if !output.status.success() {
+            eprintln!(
+                "Warning: stdin command `{}` exited with {}, feeding its output anyway",
+                command, output.status
+            );
+        }
+        pipe_to_stdin(&mut cmd, output.stdout)?;
+    }
+    if let Some(hex) = &args.stdin_hex {
+        decode_hex_to_stdin(&mut cmd, hex)?;
+    }
+    if let Some(encoded) = &args.stdin_base64 {
+        decode_base64_to_stdin(&mut cmd, encoded)?;
+    }
+    apply_stdio_redirects(&mut cmd, None, args.capture_stderr.as_deref())?;
+    if let Some(max) = args.max_processes.or(config.max_processes) {
+        limit_process_count(&mut cmd, max);
+    }
+    if !args.inherit_fds.is_empty() {
+        inherit_fds(&mut cmd, &args.inherit_fds)?;
+    }
+    let mut core_pattern = None;
+    if args.core_dump_on_crash {
+        if let Some(dir) = &config.core_dump_dir {
+            core_pattern = set_core_dump_dir(dir);
+        }
+        enable_core_dumps(&mut cmd);
+    }
 
-    for f in files {
-        cmd.arg(f);
+    let started = Instant::now();
+    let heartbeat = args.heartbeat_interval_ms.map(|ms| {
+        let (cancel, cancelled) = std::sync::mpsc::channel();
+        let thread = HeartbeatThread::start(Duration::from_millis(ms), cancelled);
+        (cancel, thread)
+    });
+    let (status, elapsed, stdout) = if args.output_on_failure_only {
+        let (status, stdout, _) = run_buffered(&mut cmd, true)?;
+        (status, started.elapsed(), Some(stdout))
+    } else if let Some(path) = &args.capture_output_if_failure {
+        let status = conditional_capture(&mut cmd, path, true)?;
+        (status, started.elapsed(), None)
+    } else {
+        let io = ChildIo {
+            capture_stdout: args.assert_output_line_count.is_some()
+                || args.assert_json_output.is_some(),
+            stdout_to_stderr: args.stdout_to_stderr,
+            timestamp_format: args
+                .log_timestamps
+                .then_some(config.log_timestamp_format.as_str()),
+            stdin_feed,
+            max_stderr_bytes: args.max_stderr_bytes,
+            cpu_profile_output: args.cpu_profile_output.as_deref(),
+            pid_file: args.write_pid_file.as_deref(),
+        };
+        drive_child(&mut cmd, io, started)?
+    };
+    if let Some((cancel, thread)) = heartbeat {
+        drop(cancel);
+        thread.join();
+    }
+    // dropping cmd closes carrier's end of the generator pipe, so a generator
+    // that outlives the interpreter gets EPIPE instead of blocking forever
+    drop(cmd);
+    if let Some(mut generator) = generator {
+        let generator_status = generator.wait()?;
+        if !generator_status.success() {
+            eprintln!("Warning: stdin generator exited with {}", generator_status);
+        }
+    }
+    if args.core_dump_on_crash {
+        report_core_dump(&status);
     }
-    if let Some(entry_file) = entry {
-        cmd.arg("-e").arg(entry_file);
+    drop(core_pattern);
+    if let Some(output) = &trace_output {
+        println!("Syscall trace written -> {}", output.display());
     }
 
-    cmd.spawn()?.wait()?;
+    let hook = if status.success() {
+        args.on_success
+    } else {
+        args.on_failure
+    };
+    if let Some(hook) = hook {
+        let exit_code = status.code().unwrap_or(-1).to_string();
+        let duration_ms = elapsed.as_millis().to_string();
+        let source_files = files
+            .iter()
+            .map(|f| f.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        run_hook(
+            &hook,
+            &[
+                ("CARRIER_EXIT_CODE", &exit_code),
+                ("CARRIER_SOURCE_FILES", &source_files),
+                ("CARRIER_DURATION_MS", &duration_ms),
+            ],
+        )?;
+    }
+
+    if let Some(limit_ms) = args.assert_duration_less_than_ms {
+        check_duration(elapsed, limit_ms)?;
+    }
+    if !args.assert_exit_code_in.is_empty() {
+        assert_exit_code_in(status.code().unwrap_or(-1), &args.assert_exit_code_in)?;
+    }
+    if let (Some(expected), Some(stdout)) = (args.assert_output_line_count, &stdout) {
+        assert_line_count(stdout, expected)?;
+    }
+    if let (Some(schema), Some(stdout)) = (&args.assert_json_output, &stdout) {
+        assert_json_output(stdout, schema)?;
+    }
     Ok(())
 }
 
-pub fn handle_vm(wasm_file: PathBuf, args: Vec<String>) -> Result<()> {
+pub fn handle_vm(args: VmArgs) -> Result<()> {
     let config = load_config("carrier.toml")?;
 
     let mut cmd = std::process::Command::new(&config.vm_path);
-    cmd.arg(wasm_file);
-    for a in args {
+    cmd.arg(&args.wasm_file);
+    for a in &args.args {
         cmd.arg(a);
     }
+    if args.line_buffered {
+        apply_line_buffer_settings(&mut cmd, &config, &config.vm_line_buffer_flag);
+    }
+    if !args.prepend_to_path.is_empty() {
+        cmd.env("PATH", build_prepended_path(&args.prepend_to_path)?);
+    }
+    let trace_output = if args.trace_syscalls {
+        let output = args
+            .trace_output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("out/vm-trace.txt"));
+        match syscall_tracer(&config) {
+            Some(tracer) => {
+                cmd = prepend_tracer(&cmd, tracer, &output)?;
+                Some(output)
+            }
+            None => {
+                eprintln!(
+                    "Warning: --trace-syscalls is only supported on Linux and macOS, ignoring it"
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
 
-    cmd.spawn()?.wait()?;
+    if let Some(hex) = &args.stdin_hex {
+        decode_hex_to_stdin(&mut cmd, hex)?;
+    }
+    apply_stdio_redirects(&mut cmd, None, args.capture_stderr.as_deref())?;
+
+    let started = Instant::now();
+    let heartbeat = args.heartbeat_interval_ms.map(|ms| {
+        let (cancel, cancelled) = std::sync::mpsc::channel();
+        let thread = HeartbeatThread::start(Duration::from_millis(ms), cancelled);
+        (cancel, thread)
+    });
+    let (status, elapsed, stdout) = if args.output_on_failure_only {
+        let (status, stdout, _) = run_buffered(&mut cmd, true)?;
+        (status, started.elapsed(), Some(stdout))
+    } else if let Some(path) = &args.capture_output_if_failure {
+        let status = conditional_capture(&mut cmd, path, true)?;
+        (status, started.elapsed(), None)
+    } else {
+        let io = ChildIo {
+            capture_stdout: args.assert_output_line_count.is_some(),
+            stdout_to_stderr: args.stdout_to_stderr,
+            timestamp_format: args
+                .log_timestamps
+                .then_some(config.log_timestamp_format.as_str()),
+            max_stderr_bytes: args.max_stderr_bytes,
+            pid_file: args.write_pid_file.as_deref(),
+            ..ChildIo::default()
+        };
+        drive_child(&mut cmd, io, started)?
+    };
+    if let Some((cancel, thread)) = heartbeat {
+        drop(cancel);
+        thread.join();
+    }
+    if let Some(output) = &trace_output {
+        println!("Syscall trace written -> {}", output.display());
+    }
+
+    let hook = if status.success() {
+        args.on_success
+    } else {
+        args.on_failure
+    };
+    if let Some(hook) = hook {
+        let exit_code = status.code().unwrap_or(-1).to_string();
+        let wasm_file = args.wasm_file.to_string_lossy();
+        let duration_ms = elapsed.as_millis().to_string();
+        run_hook(
+            &hook,
+            &[
+                ("CARRIER_EXIT_CODE", &exit_code),
+                ("CARRIER_WASM_FILE", &wasm_file),
+                ("CARRIER_DURATION_MS", &duration_ms),
+            ],
+        )?;
+    }
+
+    if let Some(limit_ms) = args.assert_within_time_ms {
+        let actual_ms = elapsed.as_millis();
+        if actual_ms > u128::from(limit_ms) {
+            let message = format!(
+                "VM took {}ms, exceeding the {}ms limit",
+                actual_ms, limit_ms
+            );
+            if args.strict {
+                anyhow::bail!(message);
+            }
+            eprintln!("Warning: {}", message);
+        }
+    }
+    if let Some(limit_ms) = args.assert_duration_less_than_ms {
+        check_duration(elapsed, limit_ms)?;
+    }
+    if !args.assert_exit_code_in.is_empty() {
+        assert_exit_code_in(status.code().unwrap_or(-1), &args.assert_exit_code_in)?;
+    }
+    if let (Some(expected), Some(stdout)) = (args.assert_output_line_count, &stdout) {
+        assert_line_count(stdout, expected)?;
+    }
     Ok(())
 }
 
@@ -158,27 +1292,18 @@ pub fn handle_config(key: Option<String>, value: Option<String>) -> Result<()> {
 
     match (key, value) {
         (Some(k), Some(v)) => {
-            match k.as_str() {
-                "compiler_path" => config.compiler_path = v.clone(),
-                "interpreter_path" => config.interpreter_path = v.clone(),
-                "vm_path" => config.vm_path = v.clone(),
-                "project_name" => config.project_name = v.clone(),
-                _ => println!("Unknown config key: {}", k),
+            if !config.set(&k, v.clone()) {
+                println!("Unknown config key: {}", k);
+                return Ok(());
             }
             save_config(&config, "carrier.toml")?;
             println!("Updated config key `{}` to `{}`", k, v);
         }
         (Some(k), None) => {
             // read config key
-            let val = match k.as_str() {
-                "compiler_path" => &config.compiler_path,
-                "interpreter_path" => &config.interpreter_path,
-                "vm_path" => &config.vm_path,
-                "project_name" => &config.project_name,
-                _ => {
-                    println!("Unknown config key: {}", k);
-                    return Ok(());
-                }
+            let Some(val) = config.get(&k) else {
+                println!("Unknown config key: {}", k);
+                return Ok(());
             };
             println!("{} = {}", k, val);
         }
@@ -190,16 +1315,125 @@ pub fn handle_config(key: Option<String>, value: Option<String>) -> Result<()> {
     Ok(())
 }
 
+pub fn handle_config_path_relative(base: PathBuf) -> Result<()> {
+    let config_path = std::fs::canonicalize("carrier.toml")
+        .context("Failed to locate config file: carrier.toml")?;
+    let base = std::fs::canonicalize(&base)
+        .with_context(|| format!("Failed to resolve base directory: {}", base.display()))?;
+
+    match pathdiff::diff_paths(&config_path, &base) {
+        Some(relative) => println!("{}", relative.display()),
+        None => {
+            eprintln!(
+                "Warning: cannot express {} relative to {}",
+                config_path.display(),
+                base.display()
+            );
+            println!("{}", config_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Environment variables carrier reads, or sets for the processes it starts.
+const ENV_VARS: &[(&str, &str)] = &[
+    (
+        "NO_COLOR",
+        "read by clap: turns off colored help and errors, like --no-ansi",
+    ),
+    (
+        "PATH",
+        "read by run and vm --prepend-to-path, which extend it for the child",
+    ),
+    (
+        "SOURCE_DATE_EPOCH",
+        "read by `build --reproducible`, set by `build --source-date`",
+    ),
+    (
+        "CARRIER_OUTPUT_PATH",
+        "set for build hooks: the output file",
+    ),
+    ("CARRIER_PROJECT_NAME", "set for build hooks: project_name"),
+    (
+        "CARRIER_BUILD_DURATION_MS",
+        "set for build hooks: compiler run time",
+    ),
+    (
+        "CARRIER_EXIT_CODE",
+        "set for run and vm hooks: the exit code",
+    ),
+    (
+        "CARRIER_SOURCE_FILES",
+        "set for run hooks: the interpreted files",
+    ),
+    ("CARRIER_WASM_FILE", "set for vm hooks: the executed module"),
+    ("CARRIER_DURATION_MS", "set for run and vm hooks: run time"),
+];
+
+pub fn handle_config_list_env_vars() -> Result<()> {
+    let config = load_config("carrier.toml")?;
+    let line_buffered = (
+        config.unbuffered_env_var.as_str(),
+        "set to 1 for the interpreter or VM by --line-buffered (unbuffered_env_var)",
+    );
+
+    for (name, description) in ENV_VARS.iter().chain([&line_buffered]) {
+        let value = std::env::var(name).unwrap_or_else(|_| "(unset)".to_string());
+        println!("{:<26} {:<12} {}", name, value, description);
+    }
+    Ok(())
+}
+
+pub fn handle_config_interpolate(template: String) -> Result<()> {
+    let config = load_config("carrier.toml")?;
+    let values = toml::Table::try_from(&config)?;
+
+    let (result, unresolved) = interpolate(&template, &values);
+    for placeholder in unresolved {
+        eprintln!(
+            "Warning: unknown or unset config placeholder `{}`",
+            placeholder
+        );
+    }
+    println!("{}", result);
+    Ok(())
+}
+
+/// Replaces every `${key}` in `template` with `values[key]`, returning the
+/// result and the placeholders left as-is because no such key is set.
+fn interpolate(template: &str, values: &toml::Table) -> (String, Vec<String>) {
+    let mut result = String::new();
+    let mut unresolved = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 1];
+        let key = &placeholder[2..placeholder.len() - 1];
+        result.push_str(&rest[..start]);
+        match values.get(key) {
+            Some(toml::Value::String(s)) => result.push_str(s),
+            Some(other) => result.push_str(&other.to_string()),
+            None => {
+                unresolved.push(placeholder.to_string());
+                result.push_str(placeholder);
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    (result, unresolved)
+}
+
 // recursively
 fn gather_xn_files<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
     let mut collected = Vec::new();
-    for entry in WalkDir::new(dir) {
-        if let Ok(e) = entry {
-            if e.file_type().is_file() {
-                if let Some(ext) = e.path().extension() {
-                    if ext == "xn" {
-                        collected.push(e.path().to_path_buf());
-                    }
+    for e in WalkDir::new(dir).into_iter().flatten() {
+        if e.file_type().is_file() {
+            if let Some(ext) = e.path().extension() {
+                if ext == "xn" {
+                    collected.push(e.path().to_path_buf());
                 }
             }
         }
@@ -207,18 +1441,277 @@ fn gather_xn_files<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
     collected
 }
 
-fn concatenate_xn_files<P: AsRef<Path>>(dir: P) -> Result<PathBuf> {
-    let xn_files = gather_xn_files(dir);
-    if xn_files.is_empty() {
-        anyhow::bail!("No .xn files found in `src/` for build.");
+/// Converts a `--source-date` value to a Unix timestamp; plain dates mean
+/// midnight UTC.
+fn parse_source_date(date: &str) -> Result<i64> {
+    let epoch = match chrono::DateTime::parse_from_rfc3339(date) {
+        Ok(datetime) => datetime.timestamp(),
+        Err(_) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| {
+                format!(
+                    "Invalid --source-date `{}` (expected YYYY-MM-DD or RFC 3339)",
+                    date
+                )
+            })?
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp(),
+    };
+    if epoch < 0 {
+        anyhow::bail!("--source-date `{}` is before 1970-01-01", date);
     }
+    Ok(epoch)
+}
+
+const BUILD_NUMBER_FILE: &str = "out/.build-number";
+
+/// Returns `explicit`, or the number after the one in `out/.build-number`,
+/// and records the result there for the next build.
+fn next_build_number(explicit: Option<u32>) -> Result<u32> {
+    let number = match explicit {
+        Some(number) => number,
+        None => match std::fs::read_to_string(BUILD_NUMBER_FILE) {
+            Ok(last) => {
+                let last: u32 = last
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid build number in {}", BUILD_NUMBER_FILE))?;
+                last + 1
+            }
+            Err(_) => 1,
+        },
+    };
+    std::fs::write(BUILD_NUMBER_FILE, format!("{}\n", number))?;
+    Ok(number)
+}
+
+/// Fills in the `--out-name-template` placeholders. `{date}` follows
+/// `SOURCE_DATE_EPOCH` when the build is reproducible.
+fn render_out_name(
+    template: &str,
+    config: &XnConfig,
+    output: &Path,
+    source_date_epoch: Option<u64>,
+) -> Result<String> {
+    let date = match source_date_epoch {
+        Some(secs) => chrono::DateTime::from_timestamp(secs as i64, 0)
+            .context("SOURCE_DATE_EPOCH out of range")?,
+        None => chrono::Utc::now(),
+    };
+    let mut name = template
+        .replace("{project_name}", &config.project_name)
+        .replace("{version}", &config.version)
+        .replace("{profile}", &config.profile)
+        .replace("{date}", &date.format("%Y-%m-%d").to_string());
+    if name.contains("{hash}") {
+        let hash = sha256_file(output)?;
+        name = name.replace("{hash}", &hash[..16]);
+    }
+    Ok(name)
+}
+
+fn resolve_build_id(id: String) -> Result<String> {
+    if id != "git" {
+        return Ok(id);
+    }
+    let out = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .context("Failed to run `git rev-parse` for --build-id git")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "`git rev-parse --short HEAD` failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+fn parse_env_pair(pair: &str) -> Result<(&str, &str)> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key, value)),
+        _ => anyhow::bail!(
+            "Invalid environment variable `{}`, expected KEY=VALUE",
+            pair
+        ),
+    }
+}
+
+/// Compiles `include` to an object in `cache_dir` unless an object for the
+/// same contents is already there, and returns the object path.
+fn precompile_include(
+    config: &XnConfig,
+    include: &Path,
+    env: &[(&str, &str)],
+    log_file: Option<&File>,
+) -> Result<PathBuf> {
+    let hash = sha256_file(include)?;
+    let stem = include
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let object = config
+        .cache_dir
+        .join(format!("{}-{}.xno", stem, &hash[..16]));
+    if object.is_file() {
+        return Ok(object);
+    }
+
+    std::fs::create_dir_all(&config.cache_dir)?;
+    let mut cmd = std::process::Command::new(&config.compiler_path);
+    cmd.arg(include)
+        .arg(&config.compiler_precompile_flag)
+        .arg("-o")
+        .arg(&object)
+        .envs(env.iter().copied());
+    let status = run_compiler(&mut cmd, None, log_file)
+        .with_context(|| format!("Failed to precompile {}", include.display()))?;
+    if !status.success() {
+        anyhow::bail!("Precompiling {} failed with {}", include.display(), status);
+    }
+    println!("Precompiled {} -> {}", include.display(), object.display());
+    Ok(object)
+}
+
+/// Whether two paths name the same file, falling back to a plain comparison
+/// when either cannot be resolved.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn print_source_stats(files: &[PathBuf]) -> Result<()> {
+    let mut stats = Vec::new();
+    for file in files {
+        let contents = std::fs::read(file)?;
+        let lines = contents.iter().filter(|&&b| b == b'\n').count();
+        stats.push((file, lines, contents.len()));
+    }
+    stats.sort_by_key(|s| std::cmp::Reverse(s.1));
+
+    println!("{:>8} {:>10}  file", "lines", "bytes");
+    for (file, lines, bytes) in &stats {
+        println!("{:>8} {:>10}  {}", lines, bytes, file.display());
+    }
+    let total_lines: usize = stats.iter().map(|s| s.1).sum();
+    let total_bytes: usize = stats.iter().map(|s| s.2).sum();
+    println!("{:>8} {:>10}  total", total_lines, total_bytes);
+    Ok(())
+}
+
+/// Prints (or writes to `output`) one `sha256sum`-style line per file.
+fn report_file_hashes(files: &[PathBuf], output: Option<&Path>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let mut report = String::new();
+    for file in files {
+        let relative = if file.is_absolute() {
+            pathdiff::diff_paths(file, &cwd).unwrap_or_else(|| file.clone())
+        } else {
+            file.clone()
+        };
+        report.push_str(&format!("{}  {}\n", sha256_file(file)?, relative.display()));
+    }
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, report)
+                .with_context(|| format!("Failed to write file hashes: {}", path.display()))?;
+            println!("File hashes written -> {}", path.display());
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
+
+fn glob_xn_files(pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut collected = Vec::new();
+    let paths =
+        glob::glob(pattern).with_context(|| format!("Invalid source glob pattern: {}", pattern))?;
+    for path in paths {
+        let path = path?;
+        if path.is_file() {
+            collected.push(path);
+        }
+    }
+    if collected.is_empty() {
+        anyhow::bail!("No files match the source glob `{}`.", pattern);
+    }
+    Ok(collected)
+}
+
+fn report_duplicate_symbols(files: &[PathBuf]) -> Result<()> {
+    let mut definitions: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for file in files {
+        let contents = std::fs::read_to_string(file)?;
+        for name in declared_functions(&contents) {
+            let defined_in = definitions.entry(name).or_default();
+            if !defined_in.contains(file) {
+                defined_in.push(file.clone());
+            }
+        }
+    }
+
+    let mut duplicates: Vec<_> = definitions
+        .into_iter()
+        .filter(|(_, defined_in)| defined_in.len() > 1)
+        .collect();
+    duplicates.sort();
+    for (name, defined_in) in duplicates {
+        let paths: Vec<_> = defined_in.iter().map(|p| p.display().to_string()).collect();
+        eprintln!(
+            "Warning: function `{}` is defined in multiple files: {}",
+            name,
+            paths.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn check_todo_comments(files: &[PathBuf], pattern: &str) -> Result<()> {
+    let pattern = pattern.to_lowercase();
+    let mut found = Vec::new();
+    for file in files {
+        let contents = std::fs::read_to_string(file)?;
+        for (index, line) in contents.lines().enumerate() {
+            if line.to_lowercase().contains(&pattern) {
+                found.push(format!(
+                    "  {}:{}: {}",
+                    file.display(),
+                    index + 1,
+                    line.trim()
+                ));
+            }
+        }
+    }
+    if !found.is_empty() {
+        anyhow::bail!(
+            "Found {} TODO comment(s):\n{}",
+            found.len(),
+            found.join("\n")
+        );
+    }
+    Ok(())
+}
+
+fn concatenate_xn_files(xn_files: &[PathBuf], injected: &[InjectedFunction]) -> Result<PathBuf> {
+    let merged_contents = merge_xn_sources(xn_files, injected)?;
 
     std::fs::create_dir_all("out")?;
 
     let merged_path = PathBuf::from("out/output.xn");
+    std::fs::write(&merged_path, merged_contents)?;
+    Ok(merged_path)
+}
+
+fn merge_xn_sources(xn_files: &[PathBuf], injected: &[InjectedFunction]) -> Result<String> {
+    if xn_files.is_empty() {
+        anyhow::bail!("No .xn files found in `src/` for build.");
+    }
 
     let mut merged_contents = String::new();
-    for file in &xn_files {
+    for file in xn_files {
         let file_contents = std::fs::read_to_string(file)?;
         merged_contents.push_str("// Start of file: ");
         merged_contents.push_str(&file.to_string_lossy());
@@ -227,6 +1720,96 @@ fn concatenate_xn_files<P: AsRef<Path>>(dir: P) -> Result<PathBuf> {
         merged_contents.push('\n');
     }
 
-    std::fs::write(&merged_path, merged_contents)?;
-    Ok(merged_path)
+    if !injected.is_empty() {
+        let existing = declared_functions(&merged_contents);
+        merged_contents.push_str("// Injected by carrier\n");
+        for function in injected {
+            if existing.contains(&function.name) {
+                eprintln!(
+                    "Warning: injected function `{}` is already defined in the sources",
+                    function.name
+                );
+            }
+            merged_contents.push_str(&function.render());
+        }
+    }
+    Ok(merged_contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_source_date_as_utc_midnight() {
+        assert_eq!(parse_source_date("2024-01-01").unwrap(), 1_704_067_200);
+        assert_eq!(parse_source_date("1970-01-01").unwrap(), 0);
+    }
+
+    #[test]
+    fn parses_rfc3339_source_date_with_offset() {
+        assert_eq!(
+            parse_source_date("2024-01-01T00:00:00Z").unwrap(),
+            1_704_067_200
+        );
+        assert_eq!(
+            parse_source_date("2024-01-01T02:00:00+02:00").unwrap(),
+            1_704_067_200
+        );
+    }
+
+    #[test]
+    fn rejects_pre_1970_and_malformed_source_dates() {
+        assert!(parse_source_date("1969-12-31").is_err());
+        assert!(parse_source_date("1969-12-31T23:59:59Z").is_err());
+        assert!(parse_source_date("01/01/2024").is_err());
+        assert!(parse_source_date("").is_err());
+    }
+
+    fn values() -> toml::Table {
+        let config = XnConfig {
+            compiler_path: "/usr/bin/xcc".to_string(),
+            project_name: "demo".to_string(),
+            ..XnConfig::default()
+        };
+        toml::Table::try_from(&config).unwrap()
+    }
+
+    #[test]
+    fn interpolates_set_and_defaulted_keys() {
+        let (result, unresolved) = interpolate(
+            "${compiler_path} -o ${project_name}.wasm ${reproducible} ${compiler_coverage_flags}",
+            &values(),
+        );
+        assert_eq!(result, "/usr/bin/xcc -o demo.wasm false [\"--coverage\"]");
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn leaves_unknown_and_unset_placeholders() {
+        let (result, unresolved) = interpolate("${nope} ${on_success} $x {y}", &values());
+        assert_eq!(result, "${nope} ${on_success} $x {y}");
+        assert_eq!(unresolved, ["${nope}", "${on_success}"]);
+    }
+
+    #[test]
+    fn leaves_unterminated_placeholder() {
+        let (result, unresolved) = interpolate("${project_name} ${compiler_path", &values());
+        assert_eq!(result, "demo ${compiler_path");
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn parses_env_pairs() {
+        assert_eq!(parse_env_pair("KEY=value").unwrap(), ("KEY", "value"));
+        assert_eq!(parse_env_pair("KEY=").unwrap(), ("KEY", ""));
+        assert_eq!(parse_env_pair("KEY=a=b").unwrap(), ("KEY", "a=b"));
+    }
+
+    #[test]
+    fn rejects_env_pairs_without_key_or_equals() {
+        assert!(parse_env_pair("KEY").is_err());
+        assert!(parse_env_pair("=value").is_err());
+        assert!(parse_env_pair("").is_err());
+    }
 }
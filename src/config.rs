@@ -1,13 +1,350 @@
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 use anyhow::{Context, Result};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct XnConfig {
     pub compiler_path: String,
     pub interpreter_path: String,
     pub vm_path: String,
     pub project_name: String,
+    /// chrono format string used by `--log-timestamps`
+    #[serde(default = "default_log_timestamp_format")]
+    pub log_timestamp_format: String,
+    /// KEY=VALUE pairs set in the compiler's environment
+    #[serde(default)]
+    pub compiler_env: Vec<String>,
+    /// Shell command run after a successful build
+    #[serde(default)]
+    pub on_success: Option<String>,
+    /// Shell command run after a failed build
+    #[serde(default)]
+    pub on_failure: Option<String>,
+    /// Compiler flag used by `--pgo-instrument`
+    #[serde(default = "default_compiler_pgo_generate_flag")]
+    pub compiler_pgo_generate_flag: String,
+    /// Compiler flag used by `--pgo-use`, given as `<flag>=<profile>`
+    #[serde(default = "default_compiler_pgo_use_flag")]
+    pub compiler_pgo_use_flag: String,
+    /// Process limit (RLIMIT_NPROC) applied to the interpreter on Linux
+    #[serde(default)]
+    pub max_processes: Option<u64>,
+    /// Directory for interpreter core dumps (sets the Linux core_pattern when permitted)
+    #[serde(default)]
+    pub core_dump_dir: Option<PathBuf>,
+    /// Environment variable set to "1" by `--line-buffered`
+    #[serde(default = "default_unbuffered_env_var")]
+    pub unbuffered_env_var: String,
+    /// Interpreter flag passed by `--line-buffered`; empty if unsupported
+    #[serde(default)]
+    pub interpreter_line_buffer_flag: String,
+    /// VM flag passed by `--line-buffered`; empty if unsupported
+    #[serde(default)]
+    pub vm_line_buffer_flag: String,
+    /// Always warn about functions defined in more than one source file
+    #[serde(default)]
+    pub report_duplicate_symbols: bool,
+    /// OCI runtime (`runc` or `crun`) used by `run --isolation-level container`
+    #[serde(default)]
+    pub container_runtime: String,
+    /// OCI bundle directory whose config.json runs the interpreter
+    #[serde(default)]
+    pub container_bundle: Option<PathBuf>,
+    /// Shared sources compiled once and cached by `build --precompile-includes`
+    #[serde(default)]
+    pub precompile_includes: Vec<PathBuf>,
+    /// Compiler flag that compiles a file to a precompiled object
+    #[serde(default = "default_compiler_precompile_flag")]
+    pub compiler_precompile_flag: String,
+    /// Compiler flag that links in a precompiled object
+    #[serde(default = "default_compiler_precompiled_flag")]
+    pub compiler_precompiled_flag: String,
+    /// Directory holding cached build artifacts
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Link-time optimization mode for builds (`thin`, `full` or `off`); when
+    /// unset, the `profile` decides
+    #[serde(default)]
+    pub lto: Option<String>,
+    /// Compiler LTO flag; `{}` is replaced by the mode
+    #[serde(default = "default_compiler_lto_flag_format")]
+    pub compiler_lto_flag_format: String,
+    /// Compiler flags for `build --coverage-instrument`; `{dir}` is replaced by `coverage_output_dir`
+    #[serde(default = "default_compiler_coverage_flags")]
+    pub compiler_coverage_flags: Vec<String>,
+    /// Directory instrumented binaries write coverage data to
+    #[serde(default = "default_coverage_output_dir")]
+    pub coverage_output_dir: PathBuf,
+    /// Always apply `build --reproducible`
+    #[serde(default)]
+    pub reproducible: bool,
+    /// Compiler flag that asks for deterministic output
+    #[serde(default = "default_compiler_reproducible_flag")]
+    pub compiler_reproducible_flag: String,
+    /// System call tracer for `--trace-syscalls`; empty picks `strace` (Linux) or `dtruss` (macOS)
+    #[serde(default)]
+    pub trace_tool_path: String,
+    /// Compiler settings applied by `build --target-wasm32-wasi`
+    #[serde(default)]
+    pub wasi_defaults: Option<WasiDefaults>,
+    /// Whether the compiler reads its source from stdin when given `-`
+    #[serde(default)]
+    pub compiler_supports_stdin: bool,
+    /// File every build appends the compiler output to
+    #[serde(default)]
+    pub compiler_log_file: Option<PathBuf>,
+    /// Compiler flag passed by `--no-ansi` to turn off colored output
+    #[serde(default = "default_compiler_no_color_flag")]
+    pub compiler_no_color_flag: String,
+    /// Warn when `build --report-symbol-count` finds more exported functions
+    #[serde(default)]
+    pub max_exported_symbols: Option<u32>,
+    /// Copy the built module here after a successful build (`build --copy-to` overrides)
+    #[serde(default)]
+    pub deploy_path: Option<PathBuf>,
+    /// Always apply `build --error-on-todo`
+    #[serde(default)]
+    pub error_on_todo: bool,
+    /// Text `--error-on-todo` looks for, matched case-insensitively
+    #[serde(default = "default_todo_pattern")]
+    pub todo_pattern: String,
+    /// Whether the compiler can link modules compiled with `build --compile-each-separately`
+    #[serde(default)]
+    pub supports_separate_compilation: bool,
+    /// Fail the build when more source files than this are gathered (`build --source-file-limit` overrides)
+    #[serde(default)]
+    pub source_file_limit: Option<u32>,
+    /// Project version, available as `{version}` in `build --out-name-template`
+    #[serde(default = "default_version")]
+    pub version: String,
+    /// Build profile (`debug` or `release`); picks the default LTO mode and
+    /// is available as `{profile}` in `build --out-name-template`
+    #[serde(default = "default_profile")]
+    pub profile: String,
+}
+
+/// Compiler settings for WASI builds, the `[wasi_defaults]` table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasiDefaults {
+    /// Flag selecting the WASI target
+    #[serde(default = "default_wasi_target_flag")]
+    pub target_flag: String,
+    /// WASI sysroot, passed as `--wasi-sysroot=<path>`
+    #[serde(default)]
+    pub sysroot_path: Option<PathBuf>,
+    /// Further flags passed to the compiler for WASI builds
+    #[serde(default)]
+    pub extra_flags: Vec<String>,
+}
+
+impl Default for WasiDefaults {
+    fn default() -> Self {
+        WasiDefaults {
+            target_flag: default_wasi_target_flag(),
+            sysroot_path: None,
+            extra_flags: Vec::new(),
+        }
+    }
+}
+
+impl WasiDefaults {
+    /// The compiler flags these settings stand for.
+    pub fn compiler_flags(&self) -> Vec<String> {
+        let mut flags = vec![self.target_flag.clone()];
+        if let Some(sysroot) = &self.sysroot_path {
+            flags.push(format!("--wasi-sysroot={}", sysroot.display()));
+        }
+        flags.extend(self.extra_flags.iter().cloned());
+        flags
+    }
+}
+
+impl Default for XnConfig {
+    fn default() -> Self {
+        Self {
+            compiler_path: String::new(),
+            interpreter_path: String::new(),
+            vm_path: String::new(),
+            project_name: String::new(),
+            log_timestamp_format: default_log_timestamp_format(),
+            compiler_env: Vec::new(),
+            on_success: None,
+            on_failure: None,
+            compiler_pgo_generate_flag: default_compiler_pgo_generate_flag(),
+            compiler_pgo_use_flag: default_compiler_pgo_use_flag(),
+            max_processes: None,
+            core_dump_dir: None,
+            unbuffered_env_var: default_unbuffered_env_var(),
+            interpreter_line_buffer_flag: String::new(),
+            vm_line_buffer_flag: String::new(),
+            report_duplicate_symbols: false,
+            container_runtime: String::new(),
+            container_bundle: None,
+            precompile_includes: Vec::new(),
+            compiler_precompile_flag: default_compiler_precompile_flag(),
+            compiler_precompiled_flag: default_compiler_precompiled_flag(),
+            cache_dir: default_cache_dir(),
+            lto: None,
+            compiler_lto_flag_format: default_compiler_lto_flag_format(),
+            compiler_coverage_flags: default_compiler_coverage_flags(),
+            coverage_output_dir: default_coverage_output_dir(),
+            reproducible: false,
+            compiler_reproducible_flag: default_compiler_reproducible_flag(),
+            trace_tool_path: String::new(),
+            wasi_defaults: None,
+            compiler_supports_stdin: false,
+            compiler_log_file: None,
+            compiler_no_color_flag: default_compiler_no_color_flag(),
+            max_exported_symbols: None,
+            deploy_path: None,
+            error_on_todo: false,
+            todo_pattern: default_todo_pattern(),
+            supports_separate_compilation: false,
+            source_file_limit: None,
+            version: default_version(),
+            profile: default_profile(),
+        }
+    }
+}
+
+impl XnConfig {
+    /// Copy of the config that is safe to publish: environment values and
+    /// hook commands may carry credentials, so they are masked.
+    pub fn redacted(&self) -> XnConfig {
+        let mut config = self.clone();
+        for pair in &mut config.compiler_env {
+            if let Some((key, _)) = pair.split_once('=') {
+                *pair = format!("{}=<redacted>", key);
+            }
+        }
+        for hook in [&mut config.on_success, &mut config.on_failure]
+            .into_iter()
+            .flatten()
+        {
+            *hook = "<redacted>".to_string();
+        }
+        config
+    }
+}
+
+/// Generates `XnConfig::get` and `XnConfig::set` from one list of the keys
+/// `carrier config <key> [value]` reads and writes as text.
+macro_rules! text_keys {
+    (strings: [$($string:ident),* $(,)?], paths: [$($path:ident),* $(,)?] $(,)?) => {
+        impl XnConfig {
+            /// The config value named `key` as text; `None` for unknown keys.
+            pub fn get(&self, key: &str) -> Option<&str> {
+                match key {
+                    $(stringify!($string) => Some(&self.$string),)*
+                    $(stringify!($path) => self.$path.to_str(),)*
+                    _ => None,
+                }
+            }
+
+            /// Sets the config value named `key`; `false` for unknown keys.
+            pub fn set(&mut self, key: &str, value: String) -> bool {
+                match key {
+                    $(stringify!($string) => self.$string = value,)*
+                    $(stringify!($path) => self.$path = PathBuf::from(value),)*
+                    _ => return false,
+                }
+                true
+            }
+        }
+    };
+}
+
+text_keys! {
+    strings: [
+        compiler_path,
+        interpreter_path,
+        vm_path,
+        project_name,
+        log_timestamp_format,
+        compiler_pgo_generate_flag,
+        compiler_pgo_use_flag,
+        unbuffered_env_var,
+        interpreter_line_buffer_flag,
+        vm_line_buffer_flag,
+        container_runtime,
+        compiler_precompile_flag,
+        compiler_precompiled_flag,
+        compiler_lto_flag_format,
+        compiler_reproducible_flag,
+        trace_tool_path,
+        compiler_no_color_flag,
+        version,
+        profile,
+        todo_pattern,
+    ],
+    paths: [cache_dir, coverage_output_dir],
+}
+
+fn default_log_timestamp_format() -> String {
+    "%Y-%m-%dT%H:%M:%S%.3fZ".to_string()
+}
+
+fn default_compiler_pgo_generate_flag() -> String {
+    "--pgo-generate".to_string()
+}
+
+fn default_compiler_pgo_use_flag() -> String {
+    "--pgo-use".to_string()
+}
+
+fn default_unbuffered_env_var() -> String {
+    "XENON_UNBUFFERED".to_string()
+}
+
+fn default_compiler_precompile_flag() -> String {
+    "--precompile".to_string()
+}
+
+fn default_compiler_precompiled_flag() -> String {
+    "--precompiled".to_string()
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from("out/cache")
+}
+
+fn default_compiler_lto_flag_format() -> String {
+    "--lto={}".to_string()
+}
+
+fn default_compiler_coverage_flags() -> Vec<String> {
+    vec!["--coverage".to_string()]
+}
+
+fn default_coverage_output_dir() -> PathBuf {
+    PathBuf::from("out/coverage")
+}
+
+fn default_compiler_reproducible_flag() -> String {
+    "--reproducible".to_string()
+}
+
+fn default_wasi_target_flag() -> String {
+    "--target=wasm32-wasi".to_string()
+}
+
+fn default_compiler_no_color_flag() -> String {
+    "--no-color".to_string()
+}
+
+fn default_todo_pattern() -> String {
+    "// TODO".to_string()
+}
+
+fn default_version() -> String {
+    "0.1.0".to_string()
+}
+
+fn default_profile() -> String {
+    "debug".to_string()
 }
 
 pub fn load_config<P: AsRef<Path>>(path: P) -> Result<XnConfig> {
@@ -18,8 +355,21 @@ pub fn load_config<P: AsRef<Path>>(path: P) -> Result<XnConfig> {
     Ok(config)
 }
 
+/// Keys without a default, which `load_config` needs even when empty.
+const REQUIRED_KEYS: [&str; 4] = [
+    "compiler_path",
+    "interpreter_path",
+    "vm_path",
+    "project_name",
+];
+
 pub fn save_config<P: AsRef<Path>>(config: &XnConfig, path: P) -> Result<()> {
-    let serialized = toml::to_string_pretty(config)?;
+    // only write back what differs from the defaults, so carrier.toml keeps
+    // to the keys the user set
+    let defaults = toml::Table::try_from(XnConfig::default())?;
+    let mut table = toml::Table::try_from(config)?;
+    table.retain(|key, value| REQUIRED_KEYS.contains(&key) || defaults.get(key) != Some(&*value));
+    let serialized = toml::to_string_pretty(&table)?;
     fs::write(&path, serialized)
         .with_context(|| format!("Failed to write config file: {}", path.as_ref().display()))?;
     Ok(())
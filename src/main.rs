@@ -1,9 +1,14 @@
-use clap::Parser;
+use clap::{ColorChoice, CommandFactory, FromArgMatches, Parser};
 
+mod assertions;
 mod commands;
 mod config;
+mod manifest;
+mod process;
+mod source;
+mod wasm;
 
-use commands::Commands;
+use commands::{Commands, ConfigAction};
 
 #[derive(Parser)]
 #[command(
@@ -12,19 +17,41 @@ use commands::Commands;
     version
 )]
 struct Cli {
+    /// Never print ANSI escape codes; builds also turn off compiler colors
+    #[arg(long, global = true)]
+    no_ansi: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
 fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    // clap colors its own help and errors, so --no-ansi must be known before parsing
+    let color = if std::env::args_os().any(|arg| arg == "--no-ansi") {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    };
+    let matches = Cli::command().color(color).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     match cli.command {
         Commands::Init { name } => commands::handle_init(name)?,
-        Commands::Build { source, output } => commands::handle_build(source, output)?,
-        Commands::Run { files, entry } => commands::handle_run(files, entry)?,
-        Commands::Vm { wasm_file, args } => commands::handle_vm(wasm_file, args)?,
-        Commands::Config { key, value } => commands::handle_config(key, value)?,
+        Commands::Build(args) => commands::handle_build(args, cli.no_ansi)?,
+        Commands::Run(args) => commands::handle_run(args)?,
+        Commands::Vm(args) => commands::handle_vm(args)?,
+        Commands::Config {
+            action: Some(ConfigAction::PathRelative { base }),
+            ..
+        } => commands::handle_config_path_relative(base)?,
+        Commands::Config {
+            action: Some(ConfigAction::Interpolate { template }),
+            ..
+        } => commands::handle_config_interpolate(template)?,
+        Commands::Config {
+            action: Some(ConfigAction::ListEnvVars),
+            ..
+        } => commands::handle_config_list_env_vars()?,
+        Commands::Config { key, value, .. } => commands::handle_config(key, value)?,
     }
 
     Ok(())
@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Project file read by the Xenon language server, written at the project root.
+pub const IDE_CONFIG_FILE: &str = ".xenon-project.json";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdeConfig {
+    pub source_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub compiler_path: String,
+    pub compiler_flags: Vec<String>,
+    pub source_files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildManifest {
+    pub inputs: Vec<ManifestFile>,
+    pub output: ManifestFile,
+    pub compiler: ManifestCompiler,
+    pub flags: Vec<String>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestCompiler {
+    pub path: String,
+    pub version: Option<String>,
+}
+
+impl ManifestFile {
+    pub fn hash<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            sha256: sha256_file(&path)?,
+        })
+    }
+}
+
+/// Describes a finished build of `output` from `inputs` by the `compiler` command.
+pub fn build_manifest(
+    inputs: &[PathBuf],
+    output: &Path,
+    compiler: &Command,
+) -> Result<BuildManifest> {
+    let inputs = inputs
+        .iter()
+        .map(ManifestFile::hash)
+        .collect::<Result<Vec<_>>>()?;
+    let compiler_path = compiler.get_program().to_string_lossy().into_owned();
+
+    Ok(BuildManifest {
+        inputs,
+        output: ManifestFile::hash(output)?,
+        compiler: ManifestCompiler {
+            version: compiler_version(&compiler_path),
+            path: compiler_path,
+        },
+        flags: compiler
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+pub fn write_manifest<P: AsRef<Path>>(manifest: &BuildManifest, path: P) -> Result<()> {
+    let serialized = serde_json::to_string_pretty(manifest)?;
+    std::fs::write(&path, serialized)
+        .with_context(|| format!("Failed to write manifest: {}", path.as_ref().display()))?;
+    Ok(())
+}
+
+pub fn write_ide_config<P: AsRef<Path>>(ide_config: &IdeConfig, path: P) -> Result<()> {
+    let serialized = serde_json::to_string_pretty(ide_config)?;
+    std::fs::write(&path, serialized)
+        .with_context(|| format!("Failed to write IDE config: {}", path.as_ref().display()))?;
+    Ok(())
+}
+
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let contents = std::fs::read(&path).with_context(|| {
+        format!(
+            "Failed to read file for hashing: {}",
+            path.as_ref().display()
+        )
+    })?;
+    Ok(format!("{:x}", Sha256::digest(&contents)))
+}
+
+// best effort: compilers without `--version` simply get no version recorded
+fn compiler_version(compiler_path: &str) -> Option<String> {
+    let out = Command::new(compiler_path).arg("--version").output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
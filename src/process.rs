@@ -0,0 +1,927 @@
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::XnConfig;
+
+/// Copies `source` to `sink` line by line, prefixing each line with the
+/// current UTC time rendered with `format`.
+pub fn timestamp_prefix_stream(
+    source: impl Read,
+    mut sink: impl Write,
+    format: &str,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(source);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        write!(sink, "{} ", chrono::Utc::now().format(format))?;
+        sink.write_all(&line)?;
+        if !line.ends_with(b"\n") {
+            sink.write_all(b"\n")?;
+        }
+        sink.flush()?;
+    }
+    Ok(())
+}
+
+/// Fails if `format` is not a valid chrono format string, which would
+/// otherwise panic on the first line `timestamp_prefix_stream` writes.
+pub fn check_timestamp_format(format: &str) -> Result<()> {
+    use chrono::format::{Item, StrftimeItems};
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        anyhow::bail!("Invalid log_timestamp_format `{}` in carrier.toml", format);
+    }
+    Ok(())
+}
+
+/// How carrier feeds a child's piped stdin.
+pub enum StdinFeed {
+    /// Forward carrier's stdin, closing the pipe after the given time.
+    CloseAfter(Duration),
+    /// Forward carrier's stdin while recording every byte to a file.
+    Record(File),
+    /// Forward carrier's stdin line by line, closing the pipe at the first
+    /// empty line.
+    UntilEmptyLine,
+}
+
+impl StdinFeed {
+    /// Starts feeding `sink` on a detached thread; carrier's stdin may never
+    /// reach EOF, so nothing waits on it.
+    pub fn start(self, sink: ChildStdin) {
+        std::thread::spawn(move || match self {
+            StdinFeed::CloseAfter(duration) => forward_stdin_for(sink, duration),
+            StdinFeed::Record(file) => {
+                let _ = tee(std::io::stdin(), sink, file);
+            }
+            StdinFeed::UntilEmptyLine => {
+                let _ = forward_until_empty_line(std::io::stdin().lock(), sink);
+            }
+        });
+    }
+}
+
+/// Copies `source` into both `primary` and `copy`, flushing after each chunk.
+pub fn tee(
+    mut source: impl Read,
+    mut primary: impl Write,
+    mut copy: impl Write,
+) -> std::io::Result<()> {
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        copy.write_all(&buf[..n])?;
+        copy.flush()?;
+        primary.write_all(&buf[..n])?;
+        primary.flush()?;
+    }
+}
+
+/// Relays `source` to carrier's stderr on a background thread until it ends.
+pub fn forward_to_stderr(mut source: impl Read + Send + 'static) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut source, &mut std::io::stderr());
+    })
+}
+
+/// Copies lines from `source` to `sink` until `source` ends or yields an
+/// empty line; the empty line itself is not forwarded.
+fn forward_until_empty_line(mut source: impl BufRead, mut sink: impl Write) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if source.read_until(b'\n', &mut line)? == 0 || line == b"\n" || line == b"\r\n" {
+            return Ok(());
+        }
+        sink.write_all(&line)?;
+        sink.flush()?;
+    }
+}
+
+/// Forwards carrier's stdin to `sink` until `duration` has elapsed, then
+/// drops `sink` so the child sees EOF even if carrier's stdin stays open.
+fn forward_stdin_for(mut sink: impl Write, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    // bounded, so a fast stdin cannot outrun a slow child without limit
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(16);
+
+    // the reader may block on stdin forever, so it is left detached; it
+    // forwards whatever arrives rather than waiting for whole lines
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0u8; 8192];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match rx.recv_timeout(remaining) {
+            Ok(chunk) => {
+                if sink.write_all(&chunk).and_then(|_| sink.flush()).is_err() {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+/// How long a child may keep running after its stderr was truncated.
+pub const STDERR_TRUNCATION_GRACE: Duration = Duration::from_secs(1);
+
+/// Forwards at most a fixed number of bytes from a child's stream on a
+/// background thread, draining and discarding everything after that.
+pub struct TruncatingForwarder {
+    handle: std::thread::JoinHandle<std::io::Result<()>>,
+    truncated_at: Arc<OnceLock<Instant>>,
+}
+
+/// Starts forwarding up to `max_bytes` of `source` to `sink`.
+pub fn truncating_forwarder(
+    mut source: impl Read + Send + 'static,
+    mut sink: impl Write + Send + 'static,
+    max_bytes: u64,
+) -> TruncatingForwarder {
+    let truncated_at = Arc::new(OnceLock::new());
+    let flag = Arc::clone(&truncated_at);
+    let handle = std::thread::spawn(move || {
+        let mut remaining = max_bytes;
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = source.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            let keep = (n as u64).min(remaining) as usize;
+            if keep > 0 {
+                sink.write_all(&buf[..keep])?;
+                sink.flush()?;
+                remaining -= keep as u64;
+            }
+            if keep < n {
+                let _ = flag.set(Instant::now());
+            }
+        }
+    });
+    TruncatingForwarder {
+        handle,
+        truncated_at,
+    }
+}
+
+impl TruncatingForwarder {
+    /// Waits for `child`, killing it once it has kept running for `grace`
+    /// after its output was truncated.
+    pub fn wait_for(&self, child: &mut Child, grace: Duration) -> std::io::Result<ExitStatus> {
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if self
+                .truncated_at
+                .get()
+                .is_some_and(|at| at.elapsed() >= grace)
+            {
+                let _ = child.kill();
+                return child.wait();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Returns whether the stream was truncated. Otherwise waits for it to
+    /// close; a truncated stream may be held open by the child's children,
+    /// so its draining thread is left detached.
+    pub fn finish(self) -> std::io::Result<bool> {
+        if self.truncated_at.get().is_some() {
+            return Ok(true);
+        }
+        self.handle.join().expect("stderr forwarder panicked")?;
+        Ok(self.truncated_at.get().is_some())
+    }
+}
+
+/// Prints `[carrier heartbeat] <elapsed>s` to stderr every interval until it
+/// is cancelled, so long silent runs do not look hung.
+pub struct HeartbeatThread {
+    handle: std::thread::JoinHandle<()>,
+}
+
+impl HeartbeatThread {
+    /// Starts beating every `interval`; sending on or dropping the other end
+    /// of `cancel` stops it.
+    pub fn start(interval: Duration, cancel: mpsc::Receiver<()>) -> HeartbeatThread {
+        let started = Instant::now();
+        let handle = std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = cancel.recv_timeout(interval) {
+                eprintln!(
+                    "[carrier heartbeat] {:.1}s",
+                    started.elapsed().as_secs_f64()
+                );
+            }
+        });
+        HeartbeatThread { handle }
+    }
+
+    /// Waits for the thread to stop after it was cancelled.
+    pub fn join(self) {
+        self.handle.join().expect("heartbeat thread panicked");
+    }
+}
+
+/// Decodes `hex` and connects the child's stdin to a pipe that yields the
+/// decoded bytes followed by end-of-file.
+pub fn decode_hex_to_stdin(cmd: &mut Command, hex: &str) -> Result<()> {
+    let bytes = hex::decode(hex).context("Invalid hex stdin data")?;
+    pipe_to_stdin(cmd, bytes)
+}
+
+/// Like [`decode_hex_to_stdin`], for standard base64 with padding.
+pub fn decode_base64_to_stdin(cmd: &mut Command, encoded: &str) -> Result<()> {
+    use base64::Engine;
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .context("Invalid base64 stdin data")?;
+    pipe_to_stdin(cmd, bytes)
+}
+
+/// Connects the child's stdin to a pipe that yields `bytes`, then end-of-file.
+pub fn pipe_to_stdin(cmd: &mut Command, bytes: Vec<u8>) -> Result<()> {
+    let (reader, mut writer) = std::io::pipe()?;
+    // written from a thread so payloads larger than the pipe buffer cannot block
+    std::thread::spawn(move || {
+        let _ = writer.write_all(&bytes);
+    });
+    cmd.stdin(reader);
+    Ok(())
+}
+
+/// Points the child's stdout and/or stderr at freshly created files.
+/// Streams without a path keep whatever `cmd` already had configured.
+pub fn apply_stdio_redirects(
+    cmd: &mut Command,
+    capture_stdout: Option<&Path>,
+    capture_stderr: Option<&Path>,
+) -> Result<()> {
+    if let Some(path) = capture_stdout {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create stdout capture: {}", path.display()))?;
+        cmd.stdout(file);
+    }
+    if let Some(path) = capture_stderr {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create stderr capture: {}", path.display()))?;
+        cmd.stderr(file);
+    }
+    Ok(())
+}
+
+/// How [`drive_child`] wires up the child's streams while it runs.
+#[derive(Default)]
+pub struct ChildIo<'a> {
+    /// Keep a copy of stdout (still shown) and return it
+    pub capture_stdout: bool,
+    /// Relay stdout to carrier's stderr
+    pub stdout_to_stderr: bool,
+    /// Prefix stdout lines with the time in this chrono format
+    pub timestamp_format: Option<&'a str>,
+    pub stdin_feed: Option<StdinFeed>,
+    /// Forward at most this many bytes of stderr, then stop the child
+    pub max_stderr_bytes: Option<u64>,
+    pub cpu_profile_output: Option<&'a Path>,
+    pub pid_file: Option<&'a Path>,
+}
+
+/// Spawns `cmd`, services its streams as described by `io` and waits for it.
+/// Returns the exit status, the time since `started` at which the child
+/// exited and, with `capture_stdout`, everything it printed to stdout.
+pub fn drive_child(
+    cmd: &mut Command,
+    io: ChildIo,
+    started: Instant,
+) -> Result<(ExitStatus, Duration, Option<String>)> {
+    if let Some(format) = io.timestamp_format {
+        check_timestamp_format(format)?;
+    }
+    // at most one of these takes stdout, in this order
+    if io.capture_stdout || io.stdout_to_stderr || io.timestamp_format.is_some() {
+        cmd.stdout(Stdio::piped());
+    }
+    if io.stdin_feed.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    if io.max_stderr_bytes.is_some() {
+        cmd.stderr(Stdio::piped());
+    }
+
+    let mut child = cmd.spawn()?;
+    let _pid_file = match io.pid_file.map(|path| PidFile::new(path, child.id())) {
+        Some(Err(e)) => {
+            // don't leave the child running unsupervised
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+        pid_file => pid_file.transpose()?,
+    };
+    let counter = io
+        .capture_stdout
+        .then(|| child.stdout.take())
+        .flatten()
+        .map(|stdout| {
+            std::thread::spawn(move || {
+                let mut captured = Vec::new();
+                tee(stdout, std::io::stdout(), &mut captured).map(|_| captured)
+            })
+        });
+    let relay = io
+        .stdout_to_stderr
+        .then(|| child.stdout.take())
+        .flatten()
+        .map(forward_to_stderr);
+    let stamper = io
+        .timestamp_format
+        .zip(child.stdout.take())
+        .map(|(format, stdout)| {
+            let format = format.to_string();
+            std::thread::spawn(move || timestamp_prefix_stream(stdout, std::io::stdout(), &format))
+        });
+    if let (Some(stdin), Some(feed)) = (child.stdin.take(), io.stdin_feed) {
+        feed.start(stdin);
+    }
+    let forwarder = child
+        .stderr
+        .take()
+        .zip(io.max_stderr_bytes)
+        .map(|(stderr, max)| truncating_forwarder(stderr, std::io::stderr(), max));
+    let profiler = io
+        .cpu_profile_output
+        .and_then(|output| start_cpu_profiler(child.id(), output));
+
+    let status = match &forwarder {
+        Some(forwarder) => forwarder.wait_for(&mut child, STDERR_TRUNCATION_GRACE)?,
+        None => child.wait()?,
+    };
+    let elapsed = started.elapsed();
+    if let (Some(forwarder), Some(max)) = (forwarder, io.max_stderr_bytes) {
+        if forwarder.finish()? {
+            eprintln!("\nstderr truncated at {} bytes", max);
+        }
+    }
+    if let Some(handle) = stamper {
+        handle.join().expect("timestamp thread panicked")?;
+    }
+    if let Some(handle) = relay {
+        handle.join().expect("stdout relay thread panicked");
+    }
+    if let (Some(mut profiler), Some(output)) = (profiler, io.cpu_profile_output) {
+        let profiler_status = profiler.wait()?;
+        if profiler_status.success() {
+            println!("CPU profile written -> {}", output.display());
+        } else {
+            eprintln!(
+                "Warning: CPU profiler exited with {}, no profile written",
+                profiler_status
+            );
+        }
+    }
+    let stdout = match counter {
+        Some(handle) => {
+            let captured = handle.join().expect("output capture thread panicked")?;
+            Some(String::from_utf8_lossy(&captured).into_owned())
+        }
+        None => None,
+    };
+    Ok((status, elapsed, stdout))
+}
+
+/// Runs `cmd` with stdout and stderr held in memory, then replays them
+/// (stdout first) unless `show_only_on_failure` is set and it succeeded.
+pub fn run_buffered(
+    cmd: &mut Command,
+    show_only_on_failure: bool,
+) -> Result<(ExitStatus, String, String)> {
+    let output = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?
+        .wait_with_output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !(show_only_on_failure && output.status.success()) {
+        print!("{}", stdout);
+        eprint!("{}", stderr);
+    }
+    Ok((output.status, stdout, stderr))
+}
+
+/// Runs `cmd` with its output shown as usual while keeping a copy of stdout
+/// and stderr in memory. If `capture` is set and the child fails, the copy
+/// (stdout first) is written to `output_path`; otherwise it is discarded.
+pub fn conditional_capture(
+    cmd: &mut Command,
+    output_path: &Path,
+    capture: bool,
+) -> Result<ExitStatus> {
+    if !capture {
+        return Ok(cmd.status()?);
+    }
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stdout = std::thread::spawn(move || {
+        let mut copy = Vec::new();
+        tee(stdout, std::io::stdout(), &mut copy).map(|_| copy)
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut copy = Vec::new();
+        tee(stderr, std::io::stderr(), &mut copy).map(|_| copy)
+    });
+    let status = child.wait()?;
+    let stdout = stdout.join().expect("stdout capture thread panicked")?;
+    let stderr = stderr.join().expect("stderr capture thread panicked")?;
+
+    if !status.success() {
+        let mut file = File::create(output_path).with_context(|| {
+            format!("Failed to create output capture: {}", output_path.display())
+        })?;
+        file.write_all(&stdout)?;
+        file.write_all(&stderr)?;
+        eprintln!("Output captured -> {}", output_path.display());
+    }
+    Ok(status)
+}
+
+/// Applies `--line-buffered`: sets the configured unbuffered environment
+/// variable and passes the tool's own line-buffer `flag`, if it has one.
+pub fn apply_line_buffer_settings(cmd: &mut Command, config: &XnConfig, flag: &str) {
+    cmd.env(&config.unbuffered_env_var, "1");
+    if !flag.is_empty() {
+        cmd.arg(flag);
+    }
+}
+
+/// The system call tracer for this platform, if there is one:
+/// `trace_tool_path` when set, otherwise `strace` on Linux and `dtruss` on macOS.
+pub fn syscall_tracer(config: &XnConfig) -> Option<&str> {
+    if !cfg!(any(target_os = "linux", target_os = "macos")) {
+        return None;
+    }
+    if !config.trace_tool_path.is_empty() {
+        Some(&config.trace_tool_path)
+    } else if cfg!(target_os = "linux") {
+        Some("strace")
+    } else {
+        Some("dtruss")
+    }
+}
+
+/// Builds a command that runs `cmd` (program, arguments, environment and
+/// working directory) under `tracer_path`, writing the trace to `output`.
+/// `dtruss` has no output option, so on macOS its stderr goes to `output`.
+pub fn prepend_tracer(cmd: &Command, tracer_path: &str, output: &Path) -> Result<Command> {
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut traced = Command::new(tracer_path);
+    if cfg!(target_os = "macos") {
+        let file = File::create(output)
+            .with_context(|| format!("Failed to create trace output: {}", output.display()))?;
+        traced.stderr(file);
+    } else {
+        traced.arg("-f").arg("-o").arg(output);
+    }
+    traced.arg(cmd.get_program()).args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => traced.env(key, value),
+            None => traced.env_remove(key),
+        };
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        traced.current_dir(dir);
+    }
+    Ok(traced)
+}
+
+/// Builds a `PATH` value with `prepend` in front of carrier's own `PATH`.
+pub fn build_prepended_path(prepend: &[PathBuf]) -> Result<OsString> {
+    let current = std::env::var_os("PATH").unwrap_or_default();
+    let dirs = prepend
+        .iter()
+        .cloned()
+        .chain(std::env::split_paths(&current));
+    std::env::join_paths(dirs)
+        .context("Cannot add a directory containing the PATH separator to PATH")
+}
+
+/// Holds a file containing a child's PID and deletes it when dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes `pid` to `path`.
+    pub fn new(path: &Path, pid: u32) -> Result<PidFile> {
+        std::fs::write(path, format!("{}\n", pid))
+            .with_context(|| format!("Failed to write PID file: {}", path.display()))?;
+        Ok(PidFile {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Runs a user hook through `sh -c` with the given extra environment.
+pub fn run_hook(hook: &str, env: &[(&str, &str)]) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .envs(env.iter().copied())
+        .status()
+        .with_context(|| format!("Failed to run hook `{}`", hook))?;
+    if !status.success() {
+        anyhow::bail!("Hook `{}` exited with {}", hook, status);
+    }
+    Ok(())
+}
+
+/// Attaches the platform profiler to `pid`, writing its report to `output`.
+/// Failures only warn, since profiling must never stop the program itself.
+pub fn start_cpu_profiler(pid: u32, output: &Path) -> Option<Child> {
+    let mut cmd = if cfg!(target_os = "linux") {
+        let mut cmd = Command::new("perf");
+        cmd.arg("record")
+            .arg("-o")
+            .arg(output)
+            .arg("--pid")
+            .arg(pid.to_string());
+        cmd
+    } else if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("sample");
+        cmd.arg(pid.to_string()).arg("-file").arg(output);
+        cmd
+    } else {
+        eprintln!("Warning: CPU profiling is not supported on this platform");
+        return None;
+    };
+
+    match cmd.spawn() {
+        Ok(profiler) => Some(profiler),
+        Err(e) => {
+            eprintln!("Warning: failed to start CPU profiler: {}", e);
+            None
+        }
+    }
+}
+
+/// Caps the number of processes the child may create via `RLIMIT_NPROC`.
+/// Only Linux is supported; elsewhere this warns and leaves `cmd` untouched.
+pub fn limit_process_count(cmd: &mut Command, max: u64) {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sys::resource::{setrlimit, Resource};
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                setrlimit(Resource::RLIMIT_NPROC, max, max).map_err(std::io::Error::from)
+            });
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (cmd, max);
+        eprintln!("Warning: --max-processes is only supported on Linux, ignoring it");
+    }
+}
+
+/// Keeps the listed file descriptors open across the child's exec by
+/// clearing `FD_CLOEXEC` on them. Fails if any of them is not open.
+/// Only Unix is supported; elsewhere this warns and leaves `cmd` untouched.
+pub fn inherit_fds(cmd: &mut Command, fds: &[u64]) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+        use std::os::unix::process::CommandExt;
+
+        let fds = fds
+            .iter()
+            .map(|&fd| {
+                let fd = i32::try_from(fd)
+                    .with_context(|| format!("Invalid file descriptor: {}", fd))?;
+                fcntl(fd, FcntlArg::F_GETFD)
+                    .with_context(|| format!("File descriptor {} is not open", fd))?;
+                Ok(fd)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // SAFETY: the closure only calls fcntl, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                for &fd in &fds {
+                    fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty())).map_err(std::io::Error::from)?;
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (cmd, fds);
+        eprintln!("Warning: --inherit-fds is only supported on Unix, ignoring it");
+    }
+    Ok(())
+}
+
+/// Moves the child into fresh PID and network namespaces before it execs.
+///
+/// After `unshare` the child forks once more so the interpreter becomes PID 1
+/// of the new namespace; the intermediate process waits for it and exits
+/// with its status. The new network namespace has nothing but a down
+/// loopback device. Requires `CAP_SYS_ADMIN` (usually root).
+pub fn isolate_namespaces(cmd: &mut Command) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sched::{unshare, CloneFlags};
+        use nix::sys::prctl::set_pdeathsig;
+        use nix::sys::signal::Signal;
+        use nix::sys::wait::{waitpid, WaitStatus};
+        use nix::unistd::{close, fork, ForkResult};
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: the closure only makes async-signal-safe calls (unshare,
+        // fork, prctl, close, waitpid and _exit)
+        unsafe {
+            cmd.pre_exec(|| {
+                unshare(CloneFlags::CLONE_NEWPID | CloneFlags::CLONE_NEWNET)?;
+                match fork()? {
+                    ForkResult::Child => {
+                        set_pdeathsig(Signal::SIGKILL)?;
+                        Ok(())
+                    }
+                    ForkResult::Parent { child } => {
+                        // release inherited descriptors (including std's exec
+                        // status pipe) so only the interpreter holds them
+                        for fd in 3..1024 {
+                            let _ = close(fd);
+                        }
+                        let code = match waitpid(child, None) {
+                            Ok(WaitStatus::Exited(_, code)) => code,
+                            Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+                            _ => 1,
+                        };
+                        nix::libc::_exit(code)
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = cmd;
+        anyhow::bail!("--isolation-level namespace is only supported on Linux");
+    }
+}
+
+/// Builds a command that runs the configured OCI bundle with `runc` or `crun`.
+///
+/// The bundle's `config.json` decides what runs inside the container, so it
+/// must invoke the interpreter on the project sources itself.
+pub fn container_command(config: &XnConfig) -> Result<Command> {
+    if config.container_runtime.is_empty() {
+        anyhow::bail!(
+            "--isolation-level container requires container_runtime (runc or crun) in carrier.toml"
+        );
+    }
+    let Some(bundle) = &config.container_bundle else {
+        anyhow::bail!("--isolation-level container requires container_bundle in carrier.toml");
+    };
+    let mut cmd = Command::new(&config.container_runtime);
+    cmd.arg("run")
+        .arg("--bundle")
+        .arg(bundle)
+        .arg(format!("carrier-{}", std::process::id()));
+    Ok(cmd)
+}
+
+/// Lifts `RLIMIT_CORE` for the child so a crash leaves a core file.
+pub fn enable_core_dumps(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use nix::sys::resource::{setrlimit, Resource, RLIM_INFINITY};
+        use std::os::unix::process::CommandExt;
+
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(|| {
+                setrlimit(Resource::RLIMIT_CORE, RLIM_INFINITY, RLIM_INFINITY)
+                    .map_err(std::io::Error::from)
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = cmd;
+        eprintln!("Warning: --core-dump-on-crash is only supported on Unix, ignoring it");
+    }
+}
+
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+
+/// Puts the kernel's previous core_pattern back when dropped.
+///
+/// The pattern is system-wide, so while the guard is live SIGINT and SIGTERM
+/// are held instead of killing carrier; the interpreter still receives them
+/// normally. Dropping the guard restores the pattern and then re-raises the
+/// first signal that arrived.
+pub struct CorePatternGuard {
+    previous: String,
+    _signals: DeferredSignals,
+}
+
+impl Drop for CorePatternGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::write(CORE_PATTERN, &self.previous) {
+            eprintln!(
+                "Warning: could not restore core_pattern to {}: {}",
+                self.previous.trim(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+static DEFERRED_SIGNAL: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+#[cfg(unix)]
+extern "C" fn defer_signal(signal: std::ffi::c_int) {
+    let _ = DEFERRED_SIGNAL.compare_exchange(
+        0,
+        signal,
+        std::sync::atomic::Ordering::SeqCst,
+        std::sync::atomic::Ordering::SeqCst,
+    );
+}
+
+/// Holds SIGINT and SIGTERM until dropped, then re-raises the first one that
+/// arrived with the previous handlers back in place.
+struct DeferredSignals {
+    #[cfg(unix)]
+    previous: Vec<(nix::sys::signal::Signal, nix::sys::signal::SigAction)>,
+}
+
+impl DeferredSignals {
+    fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+            // a handler, unlike SIG_IGN, is reset to the default on exec,
+            // so the interpreter can still be interrupted
+            let action = SigAction::new(
+                SigHandler::Handler(defer_signal),
+                SaFlags::SA_RESTART,
+                SigSet::empty(),
+            );
+            let previous = [Signal::SIGINT, Signal::SIGTERM]
+                .into_iter()
+                // SAFETY: defer_signal only stores to an atomic
+                .filter_map(|signal| {
+                    unsafe { sigaction(signal, &action) }
+                        .ok()
+                        .map(|old| (signal, old))
+                })
+                .collect();
+            Self { previous }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+}
+
+impl Drop for DeferredSignals {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use nix::sys::signal::{raise, sigaction, Signal};
+
+            for (signal, old) in &self.previous {
+                // SAFETY: puts back the handler that was installed before
+                let _ = unsafe { sigaction(*signal, old) };
+            }
+            let deferred = DEFERRED_SIGNAL.swap(0, std::sync::atomic::Ordering::SeqCst);
+            if let Ok(signal) = Signal::try_from(deferred) {
+                let _ = raise(signal);
+            }
+        }
+    }
+}
+
+/// Points the kernel's core_pattern at `dir` until the returned guard is
+/// dropped. This is system-wide and needs root, so failures only warn and
+/// leave the existing pattern in place.
+pub fn set_core_dump_dir(dir: &Path) -> Option<CorePatternGuard> {
+    if !cfg!(target_os = "linux") {
+        eprintln!("Warning: core_dump_dir is only supported on Linux, ignoring it");
+        return None;
+    }
+    let pattern = dir.join("core.%e.%p");
+    // installed before the write, so no signal lands between it and the guard
+    let signals = DeferredSignals::new();
+    let result = std::fs::read_to_string(CORE_PATTERN).and_then(|previous| {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(CORE_PATTERN, pattern.to_string_lossy().as_bytes())?;
+        Ok(previous)
+    });
+    match result {
+        Ok(previous) => Some(CorePatternGuard {
+            previous,
+            _signals: signals,
+        }),
+        Err(e) => {
+            eprintln!(
+                "Warning: could not set core_pattern to {}: {}",
+                pattern.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Tells the user where to find the core file if `status` says one was written.
+pub fn report_core_dump(status: &ExitStatus) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if !status.core_dumped() {
+            return;
+        }
+        let pattern = std::fs::read_to_string(CORE_PATTERN).ok();
+        match pattern.as_deref().map(str::trim) {
+            Some(p) if p.starts_with('|') => {
+                eprintln!("Interpreter crashed; core dump was piped to `{}`", &p[1..])
+            }
+            Some(p) => eprintln!("Interpreter crashed; core dump written to {}", p),
+            None => eprintln!("Interpreter crashed; core dump written to its working directory"),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = status;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_timestamp_formats() {
+        check_timestamp_format("%Y-%m-%dT%H:%M:%S%.3fZ").unwrap();
+        check_timestamp_format("[%H:%M]").unwrap();
+        check_timestamp_format("").unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_timestamp_formats() {
+        assert!(check_timestamp_format("%Q").is_err());
+        assert!(check_timestamp_format("%Y-%").is_err());
+    }
+
+    #[test]
+    fn prefixes_each_line() {
+        let mut out = Vec::new();
+        timestamp_prefix_stream(&b"one\ntwo"[..], &mut out, "[t]").unwrap();
+        assert_eq!(out, b"[t] one\n[t] two\n");
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+
+/// A function spliced into the merged source by `--inject-function`.
+#[derive(Debug)]
+pub struct InjectedFunction {
+    pub name: String,
+    pub signature: String,
+    pub body: String,
+}
+
+impl InjectedFunction {
+    /// Parses `name:signature:body`, e.g. `answer:() -> i32:return 42;`.
+    ///
+    /// Parameter lists contain `:` themselves, so the body starts at the
+    /// first `:` after the closing parenthesis of the signature.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid --inject-function `{}`, expected name:signature:body",
+                spec
+            )
+        };
+        let (name, rest) = spec.split_once(':').ok_or_else(invalid)?;
+        let params_end = rest.find(')').ok_or_else(invalid)?;
+        let body_sep = params_end + rest[params_end..].find(':').ok_or_else(invalid)?;
+
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            signature: rest[..body_sep].trim().to_string(),
+            body: rest[body_sep + 1..].trim().to_string(),
+        })
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "fn {}{} {{\n    {}\n}}\n",
+            self.name, self.signature, self.body
+        )
+    }
+}
+
+/// Names of the functions declared with `fn <name>(` in `source`.
+pub fn declared_functions(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for line in source.lines() {
+        let Some(rest) = line.trim_start().strip_prefix("fn ") else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        if !name.is_empty() && rest[name.len()..].trim_start().starts_with('(') {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simple_function() {
+        let f = InjectedFunction::parse("answer:() -> i32:return 42;").unwrap();
+        assert_eq!(f.name, "answer");
+        assert_eq!(f.signature, "() -> i32");
+        assert_eq!(f.body, "return 42;");
+    }
+
+    #[test]
+    fn body_starts_after_the_parameter_list() {
+        let f = InjectedFunction::parse("add:(a: i32, b: i32) -> i32:return a + b;").unwrap();
+        assert_eq!(f.name, "add");
+        assert_eq!(f.signature, "(a: i32, b: i32) -> i32");
+        assert_eq!(f.body, "return a + b;");
+    }
+
+    #[test]
+    fn body_may_contain_colons() {
+        let f = InjectedFunction::parse("log:(): print(\"a: b\");").unwrap();
+        assert_eq!(f.signature, "()");
+        assert_eq!(f.body, "print(\"a: b\");");
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for spec in [
+            "answer",
+            "answer:() -> i32",
+            ":():return 1;",
+            "answer:i32:return 1;",
+        ] {
+            assert!(InjectedFunction::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn renders_function() {
+        let f = InjectedFunction::parse("answer:() -> i32:return 42;").unwrap();
+        assert_eq!(f.render(), "fn answer() -> i32 {\n    return 42;\n}\n");
+    }
+
+    #[test]
+    fn finds_declared_functions() {
+        let source = "fn main() {\n    helper();\n}\n\n    fn helper (x: i32) {}\nfn not_a_fn\nlet fn_ptr = 1;\n";
+        assert_eq!(declared_functions(source), ["main", "helper"]);
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+const CUSTOM_SECTION_ID: u8 = 0;
+const EXPORT_SECTION_ID: u8 = 7;
+const EXPORT_KIND_FUNCTION: u8 = 0;
+
+/// Appends a custom section called `name` holding `payload` to the module at `path`.
+pub fn append_custom_section<P: AsRef<Path>>(path: P, name: &str, payload: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let mut module = std::fs::read(path)
+        .with_context(|| format!("Failed to read WASM module: {}", path.display()))?;
+    if !module.starts_with(WASM_MAGIC) {
+        anyhow::bail!("Not a WASM module: {}", path.display());
+    }
+
+    let mut contents = Vec::new();
+    write_leb128(&mut contents, name.len() as u64);
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(payload);
+
+    module.push(CUSTOM_SECTION_ID);
+    write_leb128(&mut module, contents.len() as u64);
+    module.extend_from_slice(&contents);
+
+    std::fs::write(path, module)
+        .with_context(|| format!("Failed to write WASM module: {}", path.display()))?;
+    Ok(())
+}
+
+/// Counts the function entries in the export section of the module at `path`.
+pub fn count_exported_functions<P: AsRef<Path>>(path: P) -> Result<u32> {
+    let path = path.as_ref();
+    let module = std::fs::read(path)
+        .with_context(|| format!("Failed to read WASM module: {}", path.display()))?;
+    if !module.starts_with(WASM_MAGIC) {
+        anyhow::bail!("Not a WASM module: {}", path.display());
+    }
+
+    // skip the magic number and the 4-byte version
+    let mut pos = 8;
+    while pos < module.len() {
+        let id = module[pos];
+        pos += 1;
+        let size = read_leb128(&module, &mut pos)? as usize;
+        let end = pos.checked_add(size).context("Truncated WASM section")?;
+        let section = module.get(pos..end).context("Truncated WASM section")?;
+        pos = end;
+        if id != EXPORT_SECTION_ID {
+            continue;
+        }
+
+        let mut at = 0;
+        let mut functions = 0;
+        for _ in 0..read_leb128(section, &mut at)? {
+            let name_len = read_leb128(section, &mut at)? as usize;
+            at = at.checked_add(name_len).context("Truncated WASM export")?;
+            let kind = *section.get(at).context("Truncated WASM export")?;
+            at += 1;
+            read_leb128(section, &mut at)?;
+            if kind == EXPORT_KIND_FUNCTION {
+                functions += 1;
+            }
+        }
+        return Ok(functions);
+    }
+    Ok(0)
+}
+
+fn read_leb128(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*pos).context("Truncated LEB128 value")?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift >= 64 {
+            anyhow::bail!("Malformed LEB128 value");
+        }
+    }
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn temp_module(name: &str, bytes: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("carrier-{}-{}.wasm", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn leb128_round_trips() {
+        for value in [0, 1, 127, 128, 300, 16_384, u32::MAX as u64, u64::MAX] {
+            let mut bytes = Vec::new();
+            write_leb128(&mut bytes, value);
+            let mut pos = 0;
+            assert_eq!(read_leb128(&bytes, &mut pos).unwrap(), value);
+            assert_eq!(pos, bytes.len());
+        }
+    }
+
+    #[test]
+    fn leb128_encodes_multi_byte_values() {
+        let mut bytes = Vec::new();
+        write_leb128(&mut bytes, 624_485);
+        assert_eq!(bytes, [0xe5, 0x8e, 0x26]);
+    }
+
+    #[test]
+    fn leb128_rejects_truncated_and_overlong_values() {
+        assert!(read_leb128(&[0x80], &mut 0).is_err());
+        assert!(read_leb128(&[0xff; 11], &mut 0).is_err());
+    }
+
+    #[test]
+    fn appends_custom_section() {
+        let path = temp_module("custom", HEADER);
+        append_custom_section(&path, "xenon-config", b"key = 1").unwrap();
+        let module = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = HEADER.to_vec();
+        expected.extend_from_slice(&[CUSTOM_SECTION_ID, 20, 12]);
+        expected.extend_from_slice(b"xenon-config");
+        expected.extend_from_slice(b"key = 1");
+        assert_eq!(module, expected);
+    }
+
+    #[test]
+    fn append_rejects_non_wasm_files() {
+        let path = temp_module("not-wasm", b"hello");
+        let result = append_custom_section(&path, "x", b"");
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn counts_function_exports_only() {
+        let mut module = HEADER.to_vec();
+        // a custom section before the exports is skipped
+        module.extend_from_slice(&[CUSTOM_SECTION_ID, 2, 1, b'c']);
+        // exports: func "a", memory "m", func "bb"
+        module.extend_from_slice(&[EXPORT_SECTION_ID, 14, 3]);
+        module.extend_from_slice(&[1, b'a', 0x00, 0]);
+        module.extend_from_slice(&[1, b'm', 0x02, 0]);
+        module.extend_from_slice(&[2, b'b', b'b', 0x00, 1]);
+        let path = temp_module("exports", &module);
+        let count = count_exported_functions(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count.unwrap(), 2);
+    }
+
+    #[test]
+    fn counts_zero_without_export_section() {
+        let path = temp_module("no-exports", HEADER);
+        let count = count_exported_functions(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count.unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        let mut section_size = Vec::new();
+        write_leb128(&mut section_size, u64::MAX);
+        let mut module = HEADER.to_vec();
+        module.push(EXPORT_SECTION_ID);
+        module.extend_from_slice(&section_size);
+        let path = temp_module("huge-section", &module);
+        assert!(count_exported_functions(&path).is_err());
+
+        let mut name_len = Vec::new();
+        write_leb128(&mut name_len, u64::MAX);
+        let mut module = HEADER.to_vec();
+        module.extend_from_slice(&[EXPORT_SECTION_ID, 1 + name_len.len() as u8 + 2, 1]);
+        module.extend_from_slice(&name_len);
+        module.extend_from_slice(&[0x00, 0]);
+        std::fs::write(&path, &module).unwrap();
+        let count = count_exported_functions(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(count.is_err());
+    }
+}